@@ -0,0 +1,236 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{FutureExt, Stream, StreamExt};
+use k8s_openapi::api::apps::v1::ReplicaSet;
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::core::PartialObjectMeta;
+use kube::runtime::reflector::{self, ObjectRef, Store};
+use kube::runtime::{metadata_watcher, watcher, WatchStreamExt};
+use kube::{Api, Client};
+
+// The watcher retries a failing watch at least once a minute (a 30s backoff with full jitter), so an error this recent
+// with no event since means the watch is down.
+const WATCH_ERROR_WINDOW: Duration = Duration::from_secs(120);
+
+// Cluster-wide pod store kept current by a watch, so requests never hit the apiserver. The metadata of ReplicaSets
+// and Jobs is watched as well to resolve pods to their Deployments and CronJobs.
+#[derive(Clone)]
+pub struct PodCache {
+    store: Store<Pod>,
+    replica_sets: Store<PartialObjectMeta<ReplicaSet>>,
+    jobs: Store<PartialObjectMeta<Job>>,
+    ready: Arc<AtomicBool>,
+    last_update: Arc<Mutex<Instant>>,
+    last_error: Arc<Mutex<Option<Instant>>>,
+}
+
+impl PodCache {
+    // Starts the watch in the background; the cache serves pods once the initial list has completed.
+    pub fn start(client: Client, cluster: &str) -> PodCache {
+        let (store, writer) = reflector::store();
+        let api: Api<Pod> = Api::all(client.clone());
+        let ready = Arc::new(AtomicBool::new(false));
+        let last_update = Arc::new(Mutex::new(Instant::now()));
+        let last_error = Arc::new(Mutex::new(None));
+        let events = reflector::reflector(writer, watcher(api, watcher::Config::default()))
+            .default_backoff();
+
+        let listed = Arc::clone(&ready);
+        let updated = Arc::clone(&last_update);
+        let failed = Arc::clone(&last_error);
+        let cluster = cluster.to_string();
+        let watched_cluster = cluster.clone();
+        tokio::spawn(async move {
+            events
+                .for_each(|event| {
+                    match event {
+                        Ok(event) => {
+                            if let watcher::Event::Restarted(_) = event {
+                                listed.store(true, Ordering::Relaxed);
+                            }
+                            *updated.lock().unwrap() = Instant::now();
+                        }
+                        Err(e) => {
+                            eprintln!("Pod watch error in cluster {}: {}", watched_cluster, e);
+                            *failed.lock().unwrap() = Some(Instant::now());
+                        }
+                    }
+                    futures::future::ready(())
+                })
+                .await;
+        });
+
+        let replica_sets = watch_metadata(Api::all(client.clone()), &cluster);
+        let jobs = watch_metadata(Api::all(client), &cluster);
+
+        PodCache {
+            store,
+            replica_sets,
+            jobs,
+            ready,
+            last_update,
+            last_error,
+        }
+    }
+
+    // Waits for the pods and for the ReplicaSets and Jobs their workloads are resolved through.
+    pub async fn wait_until_ready(&self) -> anyhow::Result<()> {
+        self.store.wait_until_ready().await?;
+        self.replica_sets.wait_until_ready().await?;
+        self.jobs.wait_until_ready().await?;
+        Ok(())
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    // Whether the ReplicaSets and Jobs have been listed; until then pods cannot all be resolved to their workloads.
+    pub fn owners_ready(&self) -> bool {
+        let ready = |result: Option<Result<(), reflector::store::WriterDropped>>| {
+            matches!(result, Some(Ok(())))
+        };
+        ready(self.replica_sets.wait_until_ready().now_or_never())
+            && ready(self.jobs.wait_until_ready().now_or_never())
+    }
+
+    pub fn pods(&self) -> Vec<Arc<Pod>> {
+        self.store.state()
+    }
+
+    // Owner references of a cached ReplicaSet or Job; `None` when the object is not (yet) known.
+    pub fn owner_references(
+        &self,
+        kind: &str,
+        namespace: &str,
+        name: &str,
+    ) -> Option<Vec<OwnerReference>> {
+        let metadata = match kind {
+            "ReplicaSet" => self
+                .replica_sets
+                .get(&ObjectRef::new(name).within(namespace))?
+                .metadata
+                .clone(),
+            "Job" => self
+                .jobs
+                .get(&ObjectRef::new(name).within(namespace))?
+                .metadata
+                .clone(),
+            _ => return None,
+        };
+        Some(metadata.owner_references.unwrap_or_default())
+    }
+
+    // Time since the watch last delivered an event. Bookmarks and watch restarts are not events, so the age also grows
+    // on a quiet cluster; `watch_healthy` tells the two apart.
+    pub fn age(&self) -> Duration {
+        self.last_update.lock().unwrap().elapsed()
+    }
+
+    pub fn watch_healthy(&self) -> bool {
+        match *self.last_error.lock().unwrap() {
+            Some(error) => {
+                error.elapsed() > WATCH_ERROR_WINDOW || *self.last_update.lock().unwrap() > error
+            }
+            None => true,
+        }
+    }
+}
+
+fn watch_metadata<K>(api: Api<K>, cluster: &str) -> Store<PartialObjectMeta<K>>
+where
+    K: kube::Resource<DynamicType = ()>
+        + Clone
+        + serde::de::DeserializeOwned
+        + std::fmt::Debug
+        + Send
+        + Sync
+        + 'static,
+{
+    let (store, writer) = reflector::store();
+    let events = reflector::reflector(writer, metadata_watcher(api, watcher::Config::default()))
+        .default_backoff();
+    let cluster = cluster.to_string();
+    tokio::spawn(log_errors(events, cluster, K::kind(&())));
+    store
+}
+
+async fn log_errors<T>(
+    events: impl Stream<Item = Result<T, watcher::Error>>,
+    cluster: String,
+    kind: std::borrow::Cow<'static, str>,
+) {
+    events
+        .for_each(|event| {
+            if let Err(e) = event {
+                eprintln!("{} watch error in cluster {}: {}", kind, cluster, e);
+            }
+            futures::future::ready(())
+        })
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use axum::body::{Body, Bytes};
+    use axum::extract::Query;
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+
+    // Lists nothing and then holds the watch open without sending anything, like a quiet cluster.
+    async fn quiet_list(Query(params): Query<HashMap<String, String>>) -> Response {
+        if params.get("watch").is_some_and(|watch| watch == "true") {
+            let stream = futures::stream::pending::<Result<Bytes, std::io::Error>>();
+            return Body::from_stream(stream).into_response();
+        }
+        Json(json!({
+            "apiVersion": "v1",
+            "kind": "List",
+            "metadata": {"resourceVersion": "1"},
+            "items": [],
+        }))
+        .into_response()
+    }
+
+    #[tokio::test]
+    async fn quiet_watch_stays_healthy() {
+        let router = Router::new()
+            .route("/api/v1/pods", get(quiet_list))
+            .route("/apis/apps/v1/replicasets", get(quiet_list))
+            .route("/apis/batch/v1/jobs", get(quiet_list));
+        let cache = PodCache::start(crate::tests::fake_apiserver(router).await, "test");
+        tokio::time::timeout(Duration::from_secs(5), cache.wait_until_ready())
+            .await
+            .unwrap()
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(cache.is_ready());
+        assert!(cache.owners_ready());
+        assert!(cache.watch_healthy());
+        assert!(cache.age() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn failing_watch_is_unhealthy() {
+        let cache = PodCache::start(crate::tests::fake_apiserver(Router::new()).await, "test");
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while cache.last_error.lock().unwrap().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(!cache.is_ready());
+        assert!(!cache.owners_ready());
+        assert!(!cache.watch_healthy());
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use kube::Client;
+
+use crate::cache::PodCache;
+use crate::config::ClusterConfig;
+
+// How long startup waits for the pod caches before serving; clusters still warming up answer with 503 until ready.
+const CACHE_WARMUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub struct Cluster {
+    pub name: String,
+    pub client: Client,
+    pub pod_cache: Option<PodCache>,
+}
+
+impl Cluster {
+    pub async fn connect(config: &ClusterConfig, pod_cache: bool) -> anyhow::Result<Cluster> {
+        let client = config.client().await?;
+        let pod_cache = pod_cache.then(|| PodCache::start(client.clone(), &config.name));
+        Ok(Cluster {
+            name: config.name.clone(),
+            client,
+            pod_cache,
+        })
+    }
+}
+
+// Waits for every cache at once, so one unreachable cluster delays startup by the timeout at most.
+pub async fn warm_caches(clusters: &[Cluster]) {
+    futures::future::join_all(clusters.iter().map(|cluster| async move {
+        let Some(cache) = &cluster.pod_cache else {
+            return;
+        };
+        match tokio::time::timeout(CACHE_WARMUP_TIMEOUT, cache.wait_until_ready()).await {
+            Ok(Ok(())) => println!("pod cache of cluster {} is ready", cluster.name),
+            Ok(Err(e)) => eprintln!("pod cache of cluster {} failed: {}", cluster.name, e),
+            Err(_) => eprintln!(
+                "pod cache of cluster {} is not ready yet; it keeps warming up in the background",
+                cluster.name
+            ),
+        }
+    }))
+    .await;
+}
@@ -0,0 +1,538 @@
+use std::collections::{BTreeMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{recommendations, selector};
+
+pub const DEFAULT_CLUSTER: &str = "default";
+
+// Command line flags and their environment variables. Anything left unset falls back to the config file, then to
+// the defaults in `Config::default`.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML file with settings; flags and environment variables take precedence over it
+    #[arg(long, env = "CONFIG_FILE")]
+    config: Option<PathBuf>,
+    /// Print the effective configuration as TOML and exit
+    #[arg(long)]
+    pub print_config: bool,
+    #[arg(long, env = "BIND_ADDRESS")]
+    bind_address: Option<IpAddr>,
+    #[arg(long, env = "PORT")]
+    port: Option<u16>,
+    /// Kubeconfig file; defaults to in-cluster config or $KUBECONFIG / ~/.kube/config
+    #[arg(long)]
+    kubeconfig: Option<PathBuf>,
+    /// Kubeconfig context; defaults to the current context
+    #[arg(long, env = "KUBE_CONTEXT")]
+    context: Option<String>,
+    #[arg(long, env = "MAINTAINER_LABEL_KEY")]
+    maintainer_label_key: Option<String>,
+    /// Serve pods from a watch-backed cache instead of listing per request
+    #[arg(long, env = "POD_CACHE_ENABLED")]
+    pod_cache: Option<bool>,
+    /// Add container usage from the metrics.k8s.io API to pod queries
+    #[arg(long, env = "USAGE_METRICS_ENABLED")]
+    usage_metrics: Option<bool>,
+    /// Percentile of observed usage that recommendations are sized for
+    #[arg(long, env = "RECOMMENDATION_PERCENTILE")]
+    recommendation_percentile: Option<f64>,
+    /// Fraction added on top of the percentile in recommendations
+    #[arg(long, env = "RECOMMENDATION_HEADROOM")]
+    recommendation_headroom: Option<f64>,
+    /// Usage samples a container needs before it gets a recommendation
+    #[arg(long, env = "RECOMMENDATION_MIN_SAMPLES")]
+    recommendation_min_samples: Option<usize>,
+    /// Days of snapshot history that recommendations are based on
+    #[arg(long, env = "RECOMMENDATION_WINDOW_DAYS")]
+    recommendation_window_days: Option<u64>,
+    /// SQLite database for periodic snapshots; snapshots are disabled without it
+    #[arg(long, env = "SNAPSHOT_DATABASE")]
+    snapshot_database: Option<PathBuf>,
+    #[arg(long, env = "SNAPSHOT_INTERVAL_SECONDS")]
+    snapshot_interval_seconds: Option<u64>,
+    #[arg(long, env = "SNAPSHOT_RETENTION_DAYS")]
+    snapshot_retention_days: Option<u64>,
+    /// Namespaces listed concurrently when the cache is disabled
+    #[arg(long, env = "LIST_CONCURRENCY")]
+    list_concurrency: Option<usize>,
+    /// Requests handled at the same time; further requests wait
+    #[arg(long, env = "MAX_CONCURRENT_REQUESTS")]
+    max_concurrent_requests: Option<usize>,
+    /// Namespaces queried when a request names none and does not ask for all namespaces
+    #[arg(long, env = "DEFAULT_NAMESPACES", value_delimiter = ',')]
+    default_namespaces: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub kubeconfig: Option<PathBuf>,
+    pub context: Option<String>,
+    pub maintainer_label_key: String,
+    pub pod_cache: bool,
+    pub usage_metrics: bool,
+    pub recommendation_percentile: f64,
+    pub recommendation_headroom: f64,
+    pub recommendation_min_samples: usize,
+    pub recommendation_window_days: u64,
+    pub snapshot_database: Option<PathBuf>,
+    pub snapshot_interval_seconds: u64,
+    pub snapshot_retention_days: u64,
+    pub list_concurrency: usize,
+    pub max_concurrent_requests: usize,
+    pub default_namespaces: Vec<String>,
+    pub clusters: Vec<ClusterConfig>,
+    pub pricing: Option<Pricing>,
+}
+
+// Prices of requested resources. Nodes whose `node.kubernetes.io/instance-type` label has an entry in
+// `instance_types` use those rates instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pricing {
+    pub cpu_core_hour: f64,
+    pub memory_gib_hour: f64,
+    #[serde(default)]
+    pub instance_types: BTreeMap<String, Rates>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rates {
+    pub cpu_core_hour: f64,
+    pub memory_gib_hour: f64,
+}
+
+// A cluster queried next to the others. Without any `[[clusters]]` the top-level kubeconfig and context form a
+// single cluster named `default`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClusterConfig {
+    pub name: String,
+    pub kubeconfig: Option<PathBuf>,
+    pub context: Option<String>,
+    #[serde(default)]
+    pub in_cluster: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3000,
+            kubeconfig: None,
+            context: None,
+            maintainer_label_key: "maintainer".to_string(),
+            pod_cache: true,
+            usage_metrics: true,
+            recommendation_percentile: 90.0,
+            recommendation_headroom: 0.15,
+            recommendation_min_samples: 10,
+            recommendation_window_days: 7,
+            snapshot_database: None,
+            snapshot_interval_seconds: 3600,
+            snapshot_retention_days: 30,
+            list_concurrency: 16,
+            max_concurrent_requests: 64,
+            default_namespaces: Vec::new(),
+            clusters: Vec::new(),
+            pricing: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(cli: Cli) -> anyhow::Result<Config> {
+        let config = match &cli.config {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading config file {}", path.display()))?;
+                toml::from_str(&contents)
+                    .with_context(|| format!("parsing config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+        config.merge(cli)
+    }
+
+    fn merge(mut self, cli: Cli) -> anyhow::Result<Config> {
+        self.bind_address = cli.bind_address.unwrap_or(self.bind_address);
+        self.port = cli.port.unwrap_or(self.port);
+        self.kubeconfig = cli.kubeconfig.or(self.kubeconfig);
+        self.context = cli.context.or(self.context);
+        self.maintainer_label_key = cli
+            .maintainer_label_key
+            .unwrap_or(self.maintainer_label_key);
+        self.pod_cache = cli.pod_cache.unwrap_or(self.pod_cache);
+        self.usage_metrics = cli.usage_metrics.unwrap_or(self.usage_metrics);
+        self.recommendation_percentile = cli
+            .recommendation_percentile
+            .unwrap_or(self.recommendation_percentile);
+        self.recommendation_headroom = cli
+            .recommendation_headroom
+            .unwrap_or(self.recommendation_headroom);
+        self.recommendation_min_samples = cli
+            .recommendation_min_samples
+            .unwrap_or(self.recommendation_min_samples);
+        self.recommendation_window_days = cli
+            .recommendation_window_days
+            .unwrap_or(self.recommendation_window_days);
+        self.snapshot_database = cli.snapshot_database.or(self.snapshot_database);
+        self.snapshot_interval_seconds = cli
+            .snapshot_interval_seconds
+            .unwrap_or(self.snapshot_interval_seconds);
+        self.snapshot_retention_days = cli
+            .snapshot_retention_days
+            .unwrap_or(self.snapshot_retention_days);
+        self.list_concurrency = cli.list_concurrency.unwrap_or(self.list_concurrency);
+        self.max_concurrent_requests = cli
+            .max_concurrent_requests
+            .unwrap_or(self.max_concurrent_requests);
+        self.default_namespaces = cli.default_namespaces.unwrap_or(self.default_namespaces);
+        self.validate()?;
+        Ok(self)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        selector::validate_label_key(&self.maintainer_label_key)
+            .context("maintainer_label_key must be a valid label key")?;
+        recommendations::validate(self.recommendation_percentile, self.recommendation_headroom)
+            .context("invalid recommendation settings")?;
+        if self.recommendation_min_samples == 0 {
+            bail!("recommendation_min_samples must be greater than zero");
+        }
+        if self.recommendation_window_days == 0 {
+            bail!("recommendation_window_days must be greater than zero");
+        }
+        if self.snapshot_interval_seconds == 0 {
+            bail!("snapshot_interval_seconds must be greater than zero");
+        }
+        if self.snapshot_retention_days == 0 {
+            bail!("snapshot_retention_days must be greater than zero");
+        }
+        if self.list_concurrency == 0 {
+            bail!("list_concurrency must be greater than zero");
+        }
+        if self.max_concurrent_requests == 0 {
+            bail!("max_concurrent_requests must be greater than zero");
+        }
+        if !self.clusters.is_empty() && (self.kubeconfig.is_some() || self.context.is_some()) {
+            bail!("kubeconfig and context cannot be combined with clusters; set them per cluster");
+        }
+        let mut names = HashSet::new();
+        for cluster in self.cluster_configs() {
+            if cluster.name.trim().is_empty() {
+                bail!("cluster names must not be empty");
+            }
+            if !names.insert(cluster.name.clone()) {
+                bail!("cluster {} is configured more than once", cluster.name);
+            }
+            if cluster.in_cluster && (cluster.kubeconfig.is_some() || cluster.context.is_some()) {
+                bail!(
+                    "cluster {} cannot combine in_cluster with a kubeconfig or context",
+                    cluster.name
+                );
+            }
+            if let Some(path) = &cluster.kubeconfig {
+                if !path.is_file() {
+                    bail!("kubeconfig {} does not exist", path.display());
+                }
+            }
+        }
+        if self
+            .default_namespaces
+            .iter()
+            .any(|ns| ns.trim().is_empty())
+        {
+            bail!("default_namespaces must not contain empty names");
+        }
+        if let Some(pricing) = &self.pricing {
+            validate_rates("pricing", pricing.cpu_core_hour, pricing.memory_gib_hour)?;
+            for (instance_type, rates) in &pricing.instance_types {
+                validate_rates(
+                    &format!("pricing of {}", instance_type),
+                    rates.cpu_core_hour,
+                    rates.memory_gib_hour,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn listen_address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn cluster_configs(&self) -> Vec<ClusterConfig> {
+        if !self.clusters.is_empty() {
+            return self.clusters.clone();
+        }
+        vec![ClusterConfig {
+            name: DEFAULT_CLUSTER.to_string(),
+            kubeconfig: self.kubeconfig.clone(),
+            context: self.context.clone(),
+            in_cluster: false,
+        }]
+    }
+}
+
+fn validate_rates(name: &str, cpu_core_hour: f64, memory_gib_hour: f64) -> anyhow::Result<()> {
+    for rate in [cpu_core_hour, memory_gib_hour] {
+        if !(rate.is_finite() && rate >= 0.0) {
+            bail!("{} must have non-negative rates", name);
+        }
+    }
+    Ok(())
+}
+
+impl ClusterConfig {
+    pub async fn client(&self) -> anyhow::Result<Client> {
+        let options = KubeConfigOptions {
+            context: self.context.clone(),
+            ..Default::default()
+        };
+        let kube_config = match &self.kubeconfig {
+            _ if self.in_cluster => kube::Config::incluster()?,
+            Some(path) => {
+                let kubeconfig = Kubeconfig::read_from(path)?;
+                kube::Config::from_custom_kubeconfig(kubeconfig, &options).await?
+            }
+            None if self.context.is_some() => kube::Config::from_kubeconfig(&options).await?,
+            None => kube::Config::infer().await?,
+        };
+        Ok(Client::try_from(kube_config)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster(name: &str) -> ClusterConfig {
+        ClusterConfig {
+            name: name.to_string(),
+            kubeconfig: None,
+            context: None,
+            in_cluster: true,
+        }
+    }
+
+    fn with_clusters(clusters: Vec<ClusterConfig>) -> Config {
+        Config {
+            clusters,
+            ..Default::default()
+        }
+    }
+
+    fn rejection(config: &Config) -> String {
+        format!("{:#}", config.validate().unwrap_err())
+    }
+
+    #[test]
+    fn accepts_distinct_clusters() {
+        let config = with_clusters(vec![cluster("prod"), cluster("staging")]);
+        config.validate().unwrap();
+        let names: Vec<String> = config
+            .cluster_configs()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, ["prod", "staging"]);
+        assert_eq!(Config::default().cluster_configs()[0].name, DEFAULT_CLUSTER);
+    }
+
+    #[test]
+    fn rejects_duplicate_and_empty_cluster_names() {
+        let config = with_clusters(vec![cluster("prod"), cluster("prod")]);
+        assert_eq!(
+            rejection(&config),
+            "cluster prod is configured more than once"
+        );
+        let config = with_clusters(vec![cluster(" ")]);
+        assert_eq!(rejection(&config), "cluster names must not be empty");
+    }
+
+    #[test]
+    fn rejects_in_cluster_with_a_kubeconfig_or_context() {
+        let mut with_kubeconfig = cluster("prod");
+        with_kubeconfig.kubeconfig = Some(PathBuf::from("/etc/kubeconfig"));
+        let mut with_context = cluster("staging");
+        with_context.context = Some("staging".to_string());
+        for cluster in [with_kubeconfig, with_context] {
+            let name = cluster.name.clone();
+            assert_eq!(
+                rejection(&with_clusters(vec![cluster])),
+                format!(
+                    "cluster {} cannot combine in_cluster with a kubeconfig or context",
+                    name
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_top_level_kubeconfig_or_context_next_to_clusters() {
+        let expected =
+            "kubeconfig and context cannot be combined with clusters; set them per cluster";
+        let mut config = with_clusters(vec![cluster("prod")]);
+        config.context = Some("prod".to_string());
+        assert_eq!(rejection(&config), expected);
+        let mut config = with_clusters(vec![cluster("prod")]);
+        config.kubeconfig = Some(PathBuf::from("/etc/kubeconfig"));
+        assert_eq!(rejection(&config), expected);
+    }
+
+    #[test]
+    fn rejects_a_missing_kubeconfig() {
+        let mut config = cluster("prod");
+        config.in_cluster = false;
+        config.kubeconfig = Some(PathBuf::from("/nonexistent/kubeconfig"));
+        assert_eq!(
+            rejection(&with_clusters(vec![config])),
+            "kubeconfig /nonexistent/kubeconfig does not exist"
+        );
+    }
+
+    // The only test touching the environment, so parallel tests cannot see its variables.
+    #[test]
+    fn flags_take_precedence_over_env_over_file_over_defaults() {
+        let path = std::env::temp_dir().join(format!(
+            "kube-workload-compute-details-api-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "port = 4000\nlist_concurrency = 4\nrecommendation_min_samples = 5\n",
+        )
+        .unwrap();
+        std::env::set_var("LIST_CONCURRENCY", "8");
+        std::env::set_var("RECOMMENDATION_MIN_SAMPLES", "7");
+        let cli = Cli::try_parse_from([
+            "kube-workload-compute-details-api",
+            "--config",
+            path.to_str().unwrap(),
+            "--list-concurrency",
+            "12",
+        ])
+        .unwrap();
+        std::env::remove_var("LIST_CONCURRENCY");
+        std::env::remove_var("RECOMMENDATION_MIN_SAMPLES");
+        let config = Config::load(cli);
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.list_concurrency, 12);
+        assert_eq!(config.recommendation_min_samples, 7);
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.max_concurrent_requests, 64);
+    }
+
+    #[test]
+    fn merged_flags_are_validated() {
+        let cli = Cli::try_parse_from([
+            "kube-workload-compute-details-api",
+            "--max-concurrent-requests",
+            "0",
+        ])
+        .unwrap();
+        let e = Config::default().merge(cli).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "max_concurrent_requests must be greater than zero"
+        );
+    }
+
+    type Invalidate = fn(&mut Config);
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let cases: [(Invalidate, &str); 11] = [
+            (
+                |c| c.maintainer_label_key = "-team".to_string(),
+                "maintainer_label_key must be a valid label key",
+            ),
+            (
+                |c| c.recommendation_percentile = 0.0,
+                "invalid recommendation settings: percentile must be between 1 and 100",
+            ),
+            (
+                |c| c.recommendation_headroom = -0.1,
+                "invalid recommendation settings: headroom must be a non-negative number",
+            ),
+            (
+                |c| c.recommendation_min_samples = 0,
+                "recommendation_min_samples must be greater than zero",
+            ),
+            (
+                |c| c.recommendation_window_days = 0,
+                "recommendation_window_days must be greater than zero",
+            ),
+            (
+                |c| c.snapshot_interval_seconds = 0,
+                "snapshot_interval_seconds must be greater than zero",
+            ),
+            (
+                |c| c.snapshot_retention_days = 0,
+                "snapshot_retention_days must be greater than zero",
+            ),
+            (
+                |c| c.list_concurrency = 0,
+                "list_concurrency must be greater than zero",
+            ),
+            (
+                |c| c.default_namespaces = vec!["shop".to_string(), String::new()],
+                "default_namespaces must not contain empty names",
+            ),
+            (
+                |c| {
+                    c.pricing = Some(Pricing {
+                        cpu_core_hour: -1.0,
+                        memory_gib_hour: 0.005,
+                        instance_types: BTreeMap::new(),
+                    })
+                },
+                "pricing must have non-negative rates",
+            ),
+            (
+                |c| {
+                    c.pricing = Some(Pricing {
+                        cpu_core_hour: 0.04,
+                        memory_gib_hour: 0.005,
+                        instance_types: BTreeMap::from([(
+                            "m5.xlarge".to_string(),
+                            Rates {
+                                cpu_core_hour: 0.1,
+                                memory_gib_hour: f64::NAN,
+                            },
+                        )]),
+                    })
+                },
+                "pricing of m5.xlarge must have non-negative rates",
+            ),
+        ];
+        Config::default().validate().unwrap();
+        for (invalidate, expected) in cases {
+            let mut config = Config::default();
+            invalidate(&mut config);
+            assert!(
+                rejection(&config).starts_with(expected),
+                "{} does not start with {}",
+                rejection(&config),
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use k8s_openapi::api::core::v1::Node;
+use kube::api::ListParams;
+use kube::Api;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::cluster::Cluster;
+use crate::config::{Pricing, Rates};
+use crate::nodes::INSTANCE_TYPE_LABEL;
+use crate::PodComputeInfo;
+
+const HOURS_PER_MONTH: f64 = 730.0;
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+// Estimated price of the requested resources; monthly assumes 730 hours.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+pub struct Cost {
+    pub hourly: f64,
+    pub monthly: f64,
+}
+
+impl Cost {
+    fn hourly(hourly: f64) -> Cost {
+        Cost {
+            hourly,
+            monthly: hourly * HOURS_PER_MONTH,
+        }
+    }
+
+    pub fn sum(a: Option<Cost>, b: Option<Cost>) -> Option<Cost> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(Cost::hourly(a.hourly + b.hourly)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+// Prices the effective requests of every pod, which is what the pod reserves on its node. Instance type rates need
+// the nodes' labels; when they cannot be read the default rates apply and a warning is returned.
+pub async fn add_costs(
+    cluster: &Cluster,
+    pricing: &Pricing,
+    pods: &mut [PodComputeInfo],
+) -> Option<String> {
+    let mut warning = None;
+    let mut instance_types = HashMap::new();
+    if !pricing.instance_types.is_empty() && !pods.is_empty() {
+        let api: Api<Node> = Api::all(cluster.client.clone());
+        match api.list_metadata(&ListParams::default()).await {
+            Ok(nodes) => {
+                for node in nodes.items {
+                    let instance_type = node
+                        .metadata
+                        .labels
+                        .and_then(|mut labels| labels.remove(INSTANCE_TYPE_LABEL));
+                    if let (Some(name), Some(instance_type)) = (node.metadata.name, instance_type) {
+                        instance_types.insert(name, instance_type);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("Error listing nodes of cluster {}: {}", cluster.name, e);
+                warning = Some(format!(
+                    "instance type prices are unavailable for cluster {}, default prices apply ({})",
+                    cluster.name, e
+                ));
+            }
+        }
+    }
+    for pod in pods {
+        let rates = instance_types
+            .get(&pod.node_name)
+            .and_then(|instance_type| pricing.instance_types.get(instance_type))
+            .copied()
+            .unwrap_or(Rates {
+                cpu_core_hour: pricing.cpu_core_hour,
+                memory_gib_hour: pricing.memory_gib_hour,
+            });
+        let requests = &pod.effective_requests;
+        pod.cost = Some(Cost::hourly(
+            requests.cpu_millicores as f64 / 1000.0 * rates.cpu_core_hour
+                + requests.memory_bytes as f64 / GIB * rates.memory_gib_hour,
+        ));
+    }
+    warning
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+
+    fn pricing() -> Pricing {
+        Pricing {
+            cpu_core_hour: 0.04,
+            memory_gib_hour: 0.005,
+            instance_types: BTreeMap::from([(
+                "m5.xlarge".to_string(),
+                Rates {
+                    cpu_core_hour: 0.1,
+                    memory_gib_hour: 0.01,
+                },
+            )]),
+        }
+    }
+
+    // A pod on `node` requesting one core and 1 GiB.
+    fn pod(name: &str, node: &str) -> PodComputeInfo {
+        let mut pod = crate::tests::pod_info(
+            "test",
+            "shop",
+            name,
+            vec![crate::tests::spec_container("app", "1", "1Gi")],
+        );
+        pod.node_name = node.to_string();
+        pod
+    }
+
+    fn node(name: &str, instance_type: &str) -> serde_json::Value {
+        json!({
+            "apiVersion": "meta.k8s.io/v1",
+            "kind": "PartialObjectMetadata",
+            "metadata": {"name": name, "labels": {(INSTANCE_TYPE_LABEL): instance_type}},
+        })
+    }
+
+    async fn cluster(router: Router) -> Cluster {
+        Cluster {
+            name: "test".to_string(),
+            client: crate::tests::fake_apiserver(router).await,
+            pod_cache: None,
+        }
+    }
+
+    fn hourly(pods: &[PodComputeInfo]) -> Vec<f64> {
+        pods.iter()
+            .map(|pod| (pod.cost.unwrap().hourly * 1e6).round() / 1e6)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn prices_pods_by_the_instance_type_of_their_node() {
+        let router = Router::new().route(
+            "/api/v1/nodes",
+            get(|| async {
+                Json(json!({
+                    "apiVersion": "meta.k8s.io/v1",
+                    "kind": "PartialObjectMetadataList",
+                    "metadata": {"resourceVersion": "1"},
+                    "items": [node("node-1", "m5.xlarge"), node("node-2", "c5.large")],
+                }))
+            }),
+        );
+        let cluster = cluster(router).await;
+        let mut pods = [
+            pod("priced", "node-1"),
+            pod("unpriced-type", "node-2"),
+            pod("unscheduled", ""),
+        ];
+        let warning = add_costs(&cluster, &pricing(), &mut pods).await;
+        assert_eq!(warning, None);
+        assert_eq!(hourly(&pods), [0.11, 0.045, 0.045]);
+        assert_eq!(
+            pods[0].cost.unwrap().monthly,
+            pods[0].cost.unwrap().hourly * 730.0
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_default_rates_when_nodes_cannot_be_listed() {
+        let cluster = cluster(Router::new()).await;
+        let mut pods = [pod("web", "node-1")];
+        let warning = add_costs(&cluster, &pricing(), &mut pods).await.unwrap();
+        assert!(
+            warning.starts_with(
+                "instance type prices are unavailable for cluster test, default prices apply"
+            ),
+            "{}",
+            warning
+        );
+        assert_eq!(hourly(&pods), [0.045]);
+
+        // Without instance type rates the nodes are not needed at all.
+        let defaults_only = Pricing {
+            instance_types: BTreeMap::new(),
+            ..pricing()
+        };
+        assert_eq!(add_costs(&cluster, &defaults_only, &mut pods).await, None);
+    }
+
+    #[test]
+    fn sums_optional_costs() {
+        let cost = |hourly| Some(Cost::hourly(hourly));
+        assert_eq!(Cost::sum(cost(1.0), cost(0.5)), cost(1.5));
+        assert_eq!(Cost::sum(None, cost(0.5)), cost(0.5));
+        assert_eq!(Cost::sum(cost(1.0), None), cost(1.0));
+        assert_eq!(Cost::sum(None, None), None);
+        assert_eq!(cost(2.0).unwrap().monthly, 1460.0);
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, bail};
+
+use crate::PodComputeInfo;
+
+// Cursors are the hex-encoded sort key of the last pod on a page, which keeps them opaque to clients
+// while still letting the next page resume after that pod even if pods were added or removed in between.
+pub fn encode(pod: &PodComputeInfo) -> String {
+    sort_key_string(pod)
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn decode(cursor: &str) -> anyhow::Result<String> {
+    let hex = cursor.as_bytes();
+    if !hex.len().is_multiple_of(2) {
+        bail!("invalid cursor");
+    }
+    let bytes = hex
+        .chunks_exact(2)
+        .map(|pair| Some(hex_digit(pair[0])? << 4 | hex_digit(pair[1])?))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| anyhow!("invalid cursor"))?;
+    String::from_utf8(bytes).map_err(|_| anyhow!("invalid cursor"))
+}
+
+fn hex_digit(b: u8) -> Option<u8> {
+    (b as char).to_digit(16).map(|digit| digit as u8)
+}
+
+pub fn sort(pods: &mut [PodComputeInfo]) {
+    pods.sort_by_cached_key(sort_key_string);
+}
+
+// The position and size of a requested page, checked before any pods are listed.
+#[derive(Debug)]
+pub struct Page {
+    after: Option<String>,
+    size: Option<usize>,
+}
+
+impl Page {
+    pub fn new(cursor: Option<&str>, page_size: Option<usize>) -> anyhow::Result<Page> {
+        if page_size == Some(0) {
+            bail!("page_size must be greater than zero");
+        }
+        Ok(Page {
+            after: cursor.map(decode).transpose()?,
+            size: page_size,
+        })
+    }
+}
+
+// Returns the requested page and the cursor for the next one, expecting `pods` to be sorted already.
+pub fn paginate(pods: Vec<PodComputeInfo>, page: &Page) -> (Vec<PodComputeInfo>, Option<String>) {
+    let mut remaining = pods.into_iter().skip_while(|pod| {
+        page.after
+            .as_ref()
+            .is_some_and(|after| sort_key_string(pod) <= *after)
+    });
+    let pods: Vec<PodComputeInfo> = match page.size {
+        Some(size) => remaining.by_ref().take(size).collect(),
+        None => remaining.by_ref().collect(),
+    };
+    let next_cursor = match remaining.next() {
+        Some(_) => pods.last().map(encode),
+        None => None,
+    };
+    (pods, next_cursor)
+}
+
+// A NUL separator sorts before every character that can appear in a cluster, namespace or pod name.
+fn sort_key_string(pod: &PodComputeInfo) -> String {
+    format!("{}\0{}\0{}", pod.cluster, pod.namespace, pod.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod(cluster: &str, namespace: &str, name: &str) -> PodComputeInfo {
+        crate::tests::pod_info(cluster, namespace, name, Vec::new())
+    }
+
+    fn pods() -> Vec<PodComputeInfo> {
+        let mut pods = vec![
+            pod("b", "shop", "web-1"),
+            pod("a", "shop", "web-2"),
+            pod("a", "shop", "web-1"),
+            pod("a", "shop-eu", "api"),
+            pod("a", "default", "db"),
+        ];
+        sort(&mut pods);
+        pods
+    }
+
+    fn names(pods: &[PodComputeInfo]) -> Vec<String> {
+        pods.iter()
+            .map(|pod| format!("{}/{}/{}", pod.cluster, pod.namespace, pod.name))
+            .collect()
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let pod = pod("prod-eu", "shop", "web-7d9f-x2k4");
+        let cursor = encode(&pod);
+        assert!(cursor.bytes().all(|b| b.is_ascii_hexdigit()));
+        assert_eq!(decode(&cursor).unwrap(), "prod-eu\0shop\0web-7d9f-x2k4");
+        assert_eq!(
+            decode(&cursor.to_uppercase()).unwrap(),
+            decode(&cursor).unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        for cursor in ["a", "abc", "zz", "+f", "0x", "aéa", "é0", "ff"] {
+            assert!(decode(cursor).is_err(), "{:?} was accepted", cursor);
+            assert!(Page::new(Some(cursor), None).is_err());
+        }
+        assert!(Page::new(None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn sorts_by_cluster_namespace_and_name() {
+        assert_eq!(
+            names(&pods()),
+            [
+                "a/default/db",
+                "a/shop/web-1",
+                "a/shop/web-2",
+                "a/shop-eu/api",
+                "b/shop/web-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_through_all_pods() {
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = Page::new(cursor.as_deref(), Some(2)).unwrap();
+            let (pods, next_cursor) = paginate(pods(), &page);
+            assert!(pods.len() <= 2);
+            seen.extend(names(&pods));
+            match next_cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(seen, names(&pods()));
+    }
+
+    #[test]
+    fn last_page_has_no_next_cursor() {
+        let (first, cursor) = paginate(pods(), &Page::new(None, Some(3)).unwrap());
+        assert_eq!(first.len(), 3);
+        let (last, cursor) = paginate(pods(), &Page::new(cursor.as_deref(), Some(3)).unwrap());
+        assert_eq!(names(&last), ["a/shop-eu/api", "b/shop/web-1"]);
+        assert_eq!(cursor, None);
+
+        let (all, cursor) = paginate(pods(), &Page::new(None, Some(5)).unwrap());
+        assert_eq!(all.len(), 5);
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn cursor_past_the_end_returns_an_empty_page() {
+        let cursor = encode(&pod("z", "z", "z"));
+        let (pods, next_cursor) = paginate(pods(), &Page::new(Some(&cursor), Some(2)).unwrap());
+        assert!(pods.is_empty());
+        assert_eq!(next_cursor, None);
+    }
+
+    #[test]
+    fn cursor_resumes_after_a_removed_pod() {
+        let cursor = encode(&pod("a", "shop", "web-1"));
+        let mut current = pods();
+        current.retain(|pod| pod.name != "web-1" || pod.cluster != "a");
+        let (pods, _) = paginate(current, &Page::new(Some(&cursor), Some(1)).unwrap());
+        assert_eq!(names(&pods), ["a/shop/web-2"]);
+    }
+}
@@ -0,0 +1,243 @@
+use std::error::Error as _;
+
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Unavailable(String),
+    #[error(transparent)]
+    Kube(kube::Error),
+    #[error(transparent)]
+    Internal(anyhow::Error),
+}
+
+// RFC 7807 problem details.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    type_: String,
+    title: String,
+    status: u16,
+    detail: String,
+}
+
+// A namespace (or the whole cluster when `namespace` is absent) that could not be read while others could.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct QueryError {
+    pub cluster: String,
+    pub namespace: Option<String>,
+    pub status: u16,
+    pub detail: String,
+}
+
+impl ApiError {
+    pub fn bad_request(e: impl std::fmt::Display) -> ApiError {
+        ApiError::BadRequest(e.to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Kube(e) => kube_status(e),
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<kube::Error> for ApiError {
+    fn from(e: kube::Error) -> Self {
+        ApiError::Kube(e)
+    }
+}
+
+// Kubernetes failures are often wrapped in anyhow context further down, so unwrap them to keep their status.
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<kube::Error>() {
+            Ok(e) => ApiError::Kube(e),
+            Err(e) => ApiError::Internal(e),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            eprintln!("Error: {}", self);
+        }
+        let problem = Problem {
+            type_: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+        };
+        (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+            Json(problem),
+        )
+            .into_response()
+    }
+}
+
+pub fn kube_status(e: &kube::Error) -> StatusCode {
+    match e {
+        kube::Error::Api(response) => match response.code {
+            401 => StatusCode::UNAUTHORIZED,
+            403 => StatusCode::FORBIDDEN,
+            404 => StatusCode::NOT_FOUND,
+            400 | 422 => StatusCode::BAD_REQUEST,
+            504 => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::SERVICE_UNAVAILABLE,
+        },
+        kube::Error::Auth(_) => StatusCode::UNAUTHORIZED,
+        e if is_timeout(e) => StatusCode::GATEWAY_TIMEOUT,
+        kube::Error::HyperError(_) | kube::Error::Service(_) | kube::Error::ReadEvents(_) => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn is_timeout(e: &kube::Error) -> bool {
+    let mut source = e.source();
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::TimedOut {
+                return true;
+            }
+        }
+        source = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn api_error(code: u16) -> kube::Error {
+        kube::Error::Api(kube::error::ErrorResponse {
+            status: "Failure".to_string(),
+            message: format!("status {}", code),
+            reason: "Test".to_string(),
+            code,
+        })
+    }
+
+    fn io_error(kind: std::io::ErrorKind) -> std::io::Error {
+        std::io::Error::new(kind, "connection")
+    }
+
+    #[test]
+    fn maps_kubernetes_errors_to_statuses() {
+        let cases = [
+            (api_error(401), StatusCode::UNAUTHORIZED),
+            (api_error(403), StatusCode::FORBIDDEN),
+            (api_error(404), StatusCode::NOT_FOUND),
+            (api_error(400), StatusCode::BAD_REQUEST),
+            (api_error(422), StatusCode::BAD_REQUEST),
+            (api_error(504), StatusCode::GATEWAY_TIMEOUT),
+            (api_error(500), StatusCode::SERVICE_UNAVAILABLE),
+            (api_error(429), StatusCode::SERVICE_UNAVAILABLE),
+            (
+                kube::Error::Auth(kube::client::AuthError::ExecPluginFailed),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                kube::Error::Service(Box::new(io_error(std::io::ErrorKind::ConnectionRefused))),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                kube::Error::ReadEvents(io_error(std::io::ErrorKind::UnexpectedEof)),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                kube::Error::LinesCodecMaxLineLengthExceeded,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+        for (e, status) in cases {
+            assert_eq!(kube_status(&e), status, "{}", e);
+        }
+    }
+
+    #[test]
+    fn detects_timeouts_anywhere_in_the_source_chain() {
+        let timed_out = kube::Error::Service(Box::new(io_error(std::io::ErrorKind::TimedOut)));
+        assert_eq!(kube_status(&timed_out), StatusCode::GATEWAY_TIMEOUT);
+        let nested = kube::Error::Service(Box::new(kube::Error::ReadEvents(io_error(
+            std::io::ErrorKind::TimedOut,
+        ))));
+        assert_eq!(kube_status(&nested), StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[test]
+    fn wrapped_kubernetes_errors_keep_their_status() {
+        let wrapped = Err::<(), _>(api_error(403))
+            .context("listing namespaces")
+            .unwrap_err();
+        let e = ApiError::from(wrapped);
+        assert!(matches!(e, ApiError::Kube(_)), "{:?}", e);
+        assert_eq!(e.status(), StatusCode::FORBIDDEN);
+
+        let e = ApiError::from(anyhow::anyhow!("disk full"));
+        assert!(matches!(e, ApiError::Internal(_)));
+        assert_eq!(e.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn responds_with_problem_details() {
+        let cases = [
+            (ApiError::bad_request("invalid cursor"), 400, "Bad Request"),
+            (
+                ApiError::NotFound("no snapshot".to_string()),
+                404,
+                "Not Found",
+            ),
+            (
+                ApiError::Unavailable("warming up".to_string()),
+                503,
+                "Service Unavailable",
+            ),
+            (ApiError::Kube(api_error(403)), 403, "Forbidden"),
+        ];
+        for (e, status, title) in cases {
+            let detail = e.to_string();
+            let response = e.into_response();
+            assert_eq!(response.status().as_u16(), status);
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                PROBLEM_CONTENT_TYPE
+            );
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(
+                problem,
+                serde_json::json!({
+                    "type": "about:blank",
+                    "title": title,
+                    "status": status,
+                    "detail": detail,
+                })
+            );
+        }
+    }
+}
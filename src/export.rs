@@ -0,0 +1,222 @@
+use axum::http::header::{self, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use serde::Deserialize;
+
+use crate::error::QueryError;
+use crate::PodComputeInfo;
+
+const CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+
+const CSV_HEADER: &[&str] = &[
+    "cluster",
+    "namespace",
+    "pod",
+    "node",
+    "maintainer",
+    "workload_kind",
+    "workload",
+    "container",
+    "kind",
+    "image",
+    "requested_cpu",
+    "requested_memory",
+    "limit_cpu",
+    "limit_memory",
+    "requested_cpu_millicores",
+    "requested_memory_bytes",
+    "limit_cpu_millicores",
+    "limit_memory_bytes",
+    "used_cpu_millicores",
+    "used_memory_bytes",
+];
+
+#[derive(Debug, Deserialize, Default)]
+pub struct FormatParams {
+    format: Option<String>,
+}
+
+pub fn wants_csv(params: &FormatParams, headers: &HeaderMap) -> bool {
+    if let Some(format) = &params.format {
+        return format.eq_ignore_ascii_case("csv");
+    }
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/csv"))
+}
+
+// A CSV body has no room for partial failures, so they travel in headers: `X-Partial-Failures` lists every cluster or
+// namespace that could not be read with its status, and `X-Warning-Count` counts the warnings of the JSON response.
+pub fn csv_response(
+    pods: &[PodComputeInfo],
+    next_cursor: Option<String>,
+    errors: &[QueryError],
+    warnings: &[String],
+) -> Response {
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, CSV_CONTENT_TYPE),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"pods.csv\"",
+            ),
+        ],
+        pods_csv(pods),
+    )
+        .into_response();
+    let headers = response.headers_mut();
+    if let Some(cursor) = next_cursor.and_then(|c| HeaderValue::from_str(&c).ok()) {
+        headers.insert("x-next-cursor", cursor);
+    }
+    if !errors.is_empty() {
+        let failures: Vec<String> = errors
+            .iter()
+            .map(|error| match &error.namespace {
+                Some(namespace) => format!("{}/{}={}", error.cluster, namespace, error.status),
+                None => format!("{}={}", error.cluster, error.status),
+            })
+            .collect();
+        if let Ok(failures) = HeaderValue::from_str(&failures.join(", ")) {
+            headers.insert("x-partial-failures", failures);
+        }
+    }
+    if !warnings.is_empty() {
+        headers.insert("x-warning-count", HeaderValue::from(warnings.len()));
+    }
+    response
+}
+
+// One row per container, so pod-level columns repeat for every container of the pod.
+pub fn pods_csv(pods: &[PodComputeInfo]) -> String {
+    let mut csv = String::new();
+    push_row(&mut csv, CSV_HEADER.iter().map(|h| h.to_string()));
+    for pod in pods {
+        for container in &pod.containers {
+            let resources = &container.compute_resources;
+            let quantity =
+                |q: &Option<Quantity>| q.as_ref().map(|q| q.0.clone()).unwrap_or_default();
+            let number = |n: Option<u64>| n.map(|n| n.to_string()).unwrap_or_default();
+            push_row(
+                &mut csv,
+                [
+                    pod.cluster.clone(),
+                    pod.namespace.clone(),
+                    pod.name.clone(),
+                    pod.node_name.clone(),
+                    pod.maintainer.clone(),
+                    pod.workload.kind.clone(),
+                    pod.workload.name.clone(),
+                    container.name.clone(),
+                    container.kind.as_str().to_string(),
+                    container.image.clone().unwrap_or_default(),
+                    quantity(&resources.requested_cpu),
+                    quantity(&resources.requested_memory),
+                    quantity(&resources.limit_cpu),
+                    quantity(&resources.limit_memory),
+                    number(resources.requested_cpu_millicores),
+                    number(resources.requested_memory_bytes),
+                    number(resources.limit_cpu_millicores),
+                    number(resources.limit_memory_bytes),
+                    number(container.used_cpu_millicores),
+                    number(container.used_memory_bytes),
+                ]
+                .into_iter(),
+            );
+        }
+    }
+    csv
+}
+
+fn push_row(csv: &mut String, fields: impl Iterator<Item = String>) {
+    let fields: Vec<String> = fields.map(|field| escape(&field)).collect();
+    csv.push_str(&fields.join(","));
+    csv.push_str("\r\n");
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_error(cluster: &str, namespace: Option<&str>, status: u16) -> QueryError {
+        QueryError {
+            cluster: cluster.to_string(),
+            namespace: namespace.map(str::to_string),
+            status,
+            detail: "failed".to_string(),
+        }
+    }
+
+    #[test]
+    fn csv_response_reports_partial_failures_in_headers() {
+        let errors = [
+            query_error("prod", Some("shop"), 403),
+            query_error("staging", None, 503),
+        ];
+        let warnings = ["a".to_string(), "b".to_string()];
+        let response = csv_response(&[], Some("ab".to_string()), &errors, &warnings);
+        let headers = response.headers();
+        assert_eq!(headers["x-partial-failures"], "prod/shop=403, staging=503");
+        assert_eq!(headers["x-warning-count"], "2");
+        assert_eq!(headers["x-next-cursor"], "ab");
+        assert_eq!(headers[header::CONTENT_TYPE], CSV_CONTENT_TYPE);
+    }
+
+    #[test]
+    fn complete_csv_response_has_no_failure_headers() {
+        let response = csv_response(&[], None, &[], &[]);
+        let headers = response.headers();
+        assert!(!headers.contains_key("x-partial-failures"));
+        assert!(!headers.contains_key("x-warning-count"));
+        assert!(!headers.contains_key("x-next-cursor"));
+    }
+
+    #[test]
+    fn writes_one_row_per_container() {
+        let mut app = crate::tests::spec_container("app", "100m", "64Mi");
+        app.image = Some("shop/web:1.2".to_string());
+        let best_effort = k8s_openapi::api::core::v1::Container {
+            name: "debug".to_string(),
+            ..Default::default()
+        };
+        let mut web = crate::tests::pod_info("prod", "shop", "web", vec![app, best_effort]);
+        web.node_name = "node-1".to_string();
+        web.maintainer = "team-a".to_string();
+        web.containers[0].set_usage(
+            Some(&Quantity("50m".to_string())),
+            Some(&Quantity("32Mi".to_string())),
+        );
+
+        let csv = pods_csv(&[web]);
+        let rows: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], CSV_HEADER.join(","));
+        assert_eq!(
+            rows[1],
+            "prod,shop,web,node-1,team-a,Pod,web,app,app,shop/web:1.2,100m,64Mi,,,100,67108864,,,50,33554432"
+        );
+        assert_eq!(
+            rows[2],
+            "prod,shop,web,node-1,team-a,Pod,web,debug,app,,,,,,,,,,,"
+        );
+        assert!(rows
+            .iter()
+            .all(|row| row.split(',').count() == CSV_HEADER.len()));
+    }
+
+    #[test]
+    fn escapes_fields_that_need_quoting() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("two\nlines"), "\"two\nlines\"");
+    }
+}
@@ -1,147 +1,1497 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::sync::{Arc, Mutex};
 
-use axum::extract::Json;
-use axum::response::IntoResponse;
-use axum::routing::post;
+mod cache;
+mod cluster;
+mod config;
+mod cost;
+mod cursor;
+mod error;
+mod export;
+mod metrics;
+mod namespaces;
+mod nodes;
+mod openapi;
+mod pager;
+mod quantity;
+mod quotas;
+mod recommendations;
+mod selector;
+mod snapshots;
+mod summary;
+mod usage;
+mod workloads;
+
+use axum::extract::{Json, Query, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, MethodRouter};
 use axum::Router;
+use clap::Parser;
+use cluster::Cluster;
+use config::{Cli, Config, Pricing};
+use cost::Cost;
+use error::{ApiError, QueryError};
 use futures::{stream, StreamExt};
-use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::core::v1::{Pod, ResourceRequirements};
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
-use kube::{Api, Client};
+use kube::api::ListParams;
+use kube::Api;
+use namespaces::NamespaceScope;
+use quantity::{CpuUnit, DisplayUnits, MemoryUnit};
+use schemars::JsonSchema;
+use selector::{FieldSelector, LabelRequirement, LabelSelector};
 use serde::Deserialize;
 use serde::Serialize;
+use snapshots::SnapshotStore;
+use tower::limit::ConcurrencyLimitLayer;
+use workloads::Workload;
+
+#[derive(Clone)]
+struct AppState {
+    clusters: Arc<Vec<Cluster>>,
+    snapshots: Option<SnapshotStore>,
+    config: Arc<Config>,
+}
 
 #[tokio::main]
 async fn main() {
-    let api_routes = Router::new().route("/compute-info/pods", post(get_all_pods_info));
-    let app = Router::new().nest("/api", api_routes);
+    let cli = Cli::parse();
+    let print_config = cli.print_config;
+    let config = Config::load(cli).unwrap_or_else(|e| {
+        eprintln!("invalid configuration: {:#}", e);
+        std::process::exit(2);
+    });
+    if print_config {
+        print!("{}", config.to_toml().unwrap());
+        return;
+    }
+
+    let mut clusters = Vec::new();
+    for cluster_config in config.cluster_configs() {
+        let cluster = Cluster::connect(&cluster_config, config.pod_cache)
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("cannot connect to cluster {}: {:#}", cluster_config.name, e);
+                std::process::exit(2);
+            });
+        clusters.push(cluster);
+    }
+    if config.pod_cache {
+        println!("warming pod cache");
+        cluster::warm_caches(&clusters).await;
+    }
+    let listen_address = config.listen_address();
+    let max_concurrent_requests = config.max_concurrent_requests;
+    let snapshots = config.snapshot_database.as_ref().map(|path| {
+        SnapshotStore::open(path).unwrap_or_else(|e| {
+            eprintln!("cannot open snapshot database: {:#}", e);
+            std::process::exit(2);
+        })
+    });
+    let state = AppState {
+        clusters: Arc::new(clusters),
+        snapshots: snapshots.clone(),
+        config: Arc::new(config),
+    };
+    if let Some(store) = snapshots {
+        snapshots::spawn(state.clone(), store);
+    }
+    let app = routes()
+        .into_iter()
+        .fold(Router::new(), |app, (path, route)| app.route(path, route))
+        .layer(ConcurrencyLimitLayer::new(max_concurrent_requests))
+        .with_state(state);
 
     // run it
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
+    let listener = tokio::net::TcpListener::bind(listen_address).await.unwrap();
     println!("listening on {}", listener.local_addr().unwrap());
     axum::serve(listener, app).await.unwrap();
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 struct PodComputeInfo {
+    cluster: String,
     name: String,
     namespace: String,
+    phase: PodPhase,
+    ready: bool,
     node_name: String,
     maintainer: String,
+    workload: Workload,
     containers: Vec<Container>,
+    totals: PodTotals,
+    effective_requests: EffectiveRequests,
+    cost: Option<Cost>,
     metadata: Option<Metadata>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 struct Metadata {
     labels: Option<BTreeMap<String, String>>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 struct ComputeResources {
-    requested_cpu: Quantity,
-    requested_memory: Quantity,
+    requested_cpu: Option<Quantity>,
+    requested_memory: Option<Quantity>,
+    limit_cpu: Option<Quantity>,
+    limit_memory: Option<Quantity>,
+    requested_cpu_millicores: Option<u64>,
+    requested_memory_bytes: Option<u64>,
+    limit_cpu_millicores: Option<u64>,
+    limit_memory_bytes: Option<u64>,
+    cpu_request_limit_ratio: Option<f64>,
+    memory_request_limit_ratio: Option<f64>,
+    display: DisplayResources,
+}
+
+#[derive(Debug, Serialize, Clone, Default, JsonSchema)]
+struct DisplayResources {
+    requested_cpu: Option<f64>,
+    requested_memory: Option<f64>,
+    limit_cpu: Option<f64>,
+    limit_memory: Option<f64>,
+}
+
+// Totals cover app containers and sidecars. Pod limits are only bounded when every one of them sets a limit.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+struct PodTotals {
+    requested_cpu_millicores: u64,
+    requested_memory_bytes: u64,
+    limit_cpu_millicores: Option<u64>,
+    limit_memory_bytes: Option<u64>,
+    cpu_request_limit_ratio: Option<f64>,
+    memory_request_limit_ratio: Option<f64>,
+    display: DisplayResources,
+}
+
+impl DisplayResources {
+    fn new(
+        units: DisplayUnits,
+        requested_cpu_millicores: Option<u64>,
+        requested_memory_bytes: Option<u64>,
+        limit_cpu_millicores: Option<u64>,
+        limit_memory_bytes: Option<u64>,
+    ) -> Self {
+        DisplayResources {
+            requested_cpu: requested_cpu_millicores.map(|m| units.cpu.convert_millicores(m)),
+            requested_memory: requested_memory_bytes.map(|b| units.memory.convert_bytes(b)),
+            limit_cpu: limit_cpu_millicores.map(|m| units.cpu.convert_millicores(m)),
+            limit_memory: limit_memory_bytes.map(|b| units.memory.convert_bytes(b)),
+        }
+    }
+}
+
+impl ComputeResources {
+    fn missing_requests(&self) -> Vec<String> {
+        let mut missing = Vec::new();
+        if self.requested_cpu.is_none() {
+            missing.push("cpu".to_string());
+        }
+        if self.requested_memory.is_none() {
+            missing.push("memory".to_string());
+        }
+        missing
+    }
+
+    fn new(resources: Option<&ResourceRequirements>, units: DisplayUnits) -> Self {
+        let requests = resources.and_then(|r| r.requests.as_ref());
+        let limits = resources.and_then(|r| r.limits.as_ref());
+        let requested_cpu = requests.and_then(|requests| requests.get("cpu")).cloned();
+        let requested_memory = requests
+            .and_then(|requests| requests.get("memory"))
+            .cloned();
+        let limit_cpu = limits.and_then(|limits| limits.get("cpu")).cloned();
+        let limit_memory = limits.and_then(|limits| limits.get("memory")).cloned();
+
+        let requested_cpu_millicores = requested_cpu
+            .as_ref()
+            .and_then(|q| quantity::cpu_millicores(q).ok());
+        let requested_memory_bytes = requested_memory
+            .as_ref()
+            .and_then(|q| quantity::memory_bytes(q).ok());
+        let limit_cpu_millicores = limit_cpu
+            .as_ref()
+            .and_then(|q| quantity::cpu_millicores(q).ok());
+        let limit_memory_bytes = limit_memory
+            .as_ref()
+            .and_then(|q| quantity::memory_bytes(q).ok());
+        ComputeResources {
+            requested_cpu,
+            requested_memory,
+            limit_cpu,
+            limit_memory,
+            requested_cpu_millicores,
+            requested_memory_bytes,
+            limit_cpu_millicores,
+            limit_memory_bytes,
+            cpu_request_limit_ratio: ratio(requested_cpu_millicores, limit_cpu_millicores),
+            memory_request_limit_ratio: ratio(requested_memory_bytes, limit_memory_bytes),
+            display: DisplayResources::new(
+                units,
+                requested_cpu_millicores,
+                requested_memory_bytes,
+                limit_cpu_millicores,
+                limit_memory_bytes,
+            ),
+        }
+    }
+}
+
+// Every route with its full path; the OpenAPI document is tested against this list.
+fn routes() -> Vec<(&'static str, MethodRouter<AppState>)> {
+    vec![
+        ("/api/compute-info/pods", post(get_all_pods_info)),
+        ("/api/compute-info/summary", post(summary::get_summary)),
+        (
+            "/api/compute-info/workloads",
+            post(workloads::get_workloads),
+        ),
+        ("/api/compute-info/nodes", post(nodes::get_nodes)),
+        ("/api/compute-info/quotas", post(quotas::get_quotas)),
+        (
+            "/api/compute-info/recommendations",
+            post(recommendations::get_recommendations),
+        ),
+        ("/api/compute-info/snapshots", get(snapshots::get_snapshots)),
+        ("/api/compute-info/snapshot", get(snapshots::get_snapshot)),
+        ("/api/openapi.json", get(openapi::get_openapi)),
+        ("/api/docs", get(openapi::get_docs)),
+        ("/metrics", get(metrics::get_metrics)),
+    ]
 }
 
-#[derive(Debug, Serialize, Clone)]
+// What the scheduler reserves for the pod: the larger of the init phase and the running phase, plus RuntimeClass overhead.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+struct EffectiveRequests {
+    cpu_millicores: u64,
+    memory_bytes: u64,
+    overhead_cpu_millicores: u64,
+    overhead_memory_bytes: u64,
+    display: DisplayResources,
+}
+
+impl EffectiveRequests {
+    fn new(
+        containers: &[Container],
+        overhead: Option<&BTreeMap<String, Quantity>>,
+        units: DisplayUnits,
+    ) -> Self {
+        let overhead_cpu_millicores = overhead
+            .and_then(|overhead| overhead.get("cpu"))
+            .and_then(|q| quantity::cpu_millicores(q).ok())
+            .unwrap_or(0);
+        let overhead_memory_bytes = overhead
+            .and_then(|overhead| overhead.get("memory"))
+            .and_then(|q| quantity::memory_bytes(q).ok())
+            .unwrap_or(0);
+        let cpu_millicores =
+            effective_request(containers, |r| r.requested_cpu_millicores) + overhead_cpu_millicores;
+        let memory_bytes =
+            effective_request(containers, |r| r.requested_memory_bytes) + overhead_memory_bytes;
+        EffectiveRequests {
+            cpu_millicores,
+            memory_bytes,
+            overhead_cpu_millicores,
+            overhead_memory_bytes,
+            display: DisplayResources::new(
+                units,
+                Some(cpu_millicores),
+                Some(memory_bytes),
+                None,
+                None,
+            ),
+        }
+    }
+}
+
+// Mirrors the scheduler's per-resource request calculation for pods with init containers and native sidecars.
+fn effective_request(
+    containers: &[Container],
+    request: impl Fn(&ComputeResources) -> Option<u64>,
+) -> u64 {
+    let request_of = |container: &Container| request(&container.compute_resources).unwrap_or(0);
+    let mut running: u64 = containers
+        .iter()
+        .filter(|c| c.kind == ContainerKind::App)
+        .map(request_of)
+        .sum();
+    let mut sidecars = 0;
+    let mut init_peak = 0;
+    for container in containers.iter().filter(|c| c.kind != ContainerKind::App) {
+        let init_phase = if container.kind == ContainerKind::Sidecar {
+            running += request_of(container);
+            sidecars += request_of(container);
+            sidecars
+        } else {
+            request_of(container) + sidecars
+        };
+        init_peak = init_peak.max(init_phase);
+    }
+    running.max(init_peak)
+}
+
+impl PodTotals {
+    fn new(containers: &[Container], units: DisplayUnits) -> Self {
+        let resources = || {
+            containers
+                .iter()
+                .filter(|c| c.kind != ContainerKind::Init)
+                .map(|c| &c.compute_resources)
+        };
+        let requested_cpu_millicores = resources().filter_map(|r| r.requested_cpu_millicores).sum();
+        let requested_memory_bytes = resources().filter_map(|r| r.requested_memory_bytes).sum();
+        let limit_cpu_millicores = resources().map(|r| r.limit_cpu_millicores).sum();
+        let limit_memory_bytes = resources().map(|r| r.limit_memory_bytes).sum();
+        PodTotals {
+            requested_cpu_millicores,
+            requested_memory_bytes,
+            limit_cpu_millicores,
+            limit_memory_bytes,
+            cpu_request_limit_ratio: ratio(Some(requested_cpu_millicores), limit_cpu_millicores),
+            memory_request_limit_ratio: ratio(Some(requested_memory_bytes), limit_memory_bytes),
+            display: DisplayResources::new(
+                units,
+                Some(requested_cpu_millicores),
+                Some(requested_memory_bytes),
+                limit_cpu_millicores,
+                limit_memory_bytes,
+            ),
+        }
+    }
+}
+
+fn ratio(request: Option<u64>, limit: Option<u64>) -> Option<f64> {
+    match (request, limit) {
+        (Some(request), Some(limit)) if limit > 0 => Some(request as f64 / limit as f64),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
 struct Container {
     name: String,
     image: Option<String>,
+    kind: ContainerKind,
     compute_resources: ComputeResources,
+    missing_requests: Vec<String>,
+    used_cpu: Option<Quantity>,
+    used_memory: Option<Quantity>,
+    used_cpu_millicores: Option<u64>,
+    used_memory_bytes: Option<u64>,
+    cpu_usage_request_ratio: Option<f64>,
+    memory_usage_request_ratio: Option<f64>,
+}
+
+impl Container {
+    fn set_usage(&mut self, cpu: Option<&Quantity>, memory: Option<&Quantity>) {
+        let resources = &self.compute_resources;
+        self.used_cpu = cpu.cloned();
+        self.used_memory = memory.cloned();
+        self.used_cpu_millicores = cpu.and_then(|q| quantity::cpu_millicores(q).ok());
+        self.used_memory_bytes = memory.and_then(|q| quantity::memory_bytes(q).ok());
+        self.cpu_usage_request_ratio =
+            ratio(self.used_cpu_millicores, resources.requested_cpu_millicores);
+        self.memory_usage_request_ratio =
+            ratio(self.used_memory_bytes, resources.requested_memory_bytes);
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum ContainerKind {
+    App,
+    Init,
+    Sidecar,
+}
+
+impl ContainerKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ContainerKind::App => "app",
+            ContainerKind::Init => "init",
+            ContainerKind::Sidecar => "sidecar",
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Default, JsonSchema)]
 struct PodComputeInfoRequestBody {
+    clusters: Option<Vec<String>>,
     maintainers: Option<Vec<String>>,
+    #[serde(default)]
     namespaces: Vec<String>,
+    all_namespaces: Option<bool>,
+    exclude_namespaces: Option<Vec<String>>,
+    label_selector: Option<String>,
+    field_selector: Option<String>,
+    cpu_unit: Option<CpuUnit>,
+    memory_unit: Option<MemoryUnit>,
+    phases: Option<Vec<PodPhase>>,
+    exclude_terminating: Option<bool>,
+    require_ready: Option<bool>,
+    page_size: Option<usize>,
+    cursor: Option<String>,
 }
 
-async fn get_all_pods_info(
-    Json(request_body): Json<PodComputeInfoRequestBody>,
-) -> impl IntoResponse {
-    let namespaces = request_body.namespaces;
-    let compute_info = get_pods_info(namespaces, Vec::new()).await.unwrap();
-    Json(compute_info)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, JsonSchema)]
+enum PodPhase {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Unknown,
+}
+
+impl PodPhase {
+    fn of(pod: &Pod) -> PodPhase {
+        match pod
+            .status
+            .as_ref()
+            .and_then(|status| status.phase.as_deref())
+        {
+            Some("Pending") => PodPhase::Pending,
+            Some("Running") => PodPhase::Running,
+            Some("Succeeded") => PodPhase::Succeeded,
+            Some("Failed") => PodPhase::Failed,
+            _ => PodPhase::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+struct PodComputeInfoResponse {
+    filter: AppliedFilter,
+    cache_age_seconds: Option<f64>,
+    watch_healthy: Option<bool>,
+    units: DisplayUnits,
+    warnings: Vec<String>,
+    errors: Vec<QueryError>,
+    next_cursor: Option<String>,
+    pods: Vec<PodComputeInfo>,
 }
 
-async fn get_pods_info(
+#[derive(Debug, Serialize, JsonSchema)]
+struct AppliedFilter {
+    clusters: Vec<String>,
     namespaces: Vec<String>,
+    all_namespaces: bool,
+    exclude_namespaces: Vec<String>,
+    maintainer_label_key: String,
     maintainers: Vec<String>,
-) -> anyhow::Result<Vec<PodComputeInfo>> {
-    let client = Client::try_default().await?;
+    label_selector: Option<String>,
+    field_selector: Option<String>,
+    phases: Vec<PodPhase>,
+    exclude_terminating: bool,
+    require_ready: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PodQuery {
+    clusters: Vec<String>,
+    namespaces: Vec<String>,
+    all_namespaces: bool,
+    exclude_namespaces: Vec<String>,
+    label_selector: LabelSelector,
+    field_selector: FieldSelector,
+    maintainer_label_key: String,
+    units: DisplayUnits,
+    phases: Vec<PodPhase>,
+    exclude_terminating: bool,
+    require_ready: bool,
+    list_concurrency: usize,
+    usage_metrics: bool,
+    pricing: Option<Pricing>,
+}
+
+impl PodQuery {
+    // Applies the whole query locally, for pods that did not come filtered from the apiserver.
+    fn matches(&self, pod: &Pod) -> bool {
+        let labels = pod.metadata.labels.clone().unwrap_or_default();
+        namespaces::in_scope(
+            pod.metadata.namespace.as_deref().unwrap_or_default(),
+            &self.namespaces,
+            self.all_namespaces,
+            &self.exclude_namespaces,
+        ) && self.label_selector.matches(&labels)
+            && self.field_selector.matches_pod(pod)
+            && self.matches_lifecycle(pod)
+    }
+
+    fn matches_lifecycle(&self, pod: &Pod) -> bool {
+        if !self.phases.contains(&PodPhase::of(pod)) {
+            return false;
+        }
+        if self.exclude_terminating && pod.metadata.deletion_timestamp.is_some() {
+            return false;
+        }
+        !self.require_ready || is_ready(pod)
+    }
+
+    fn list_params(&self) -> ListParams {
+        let mut list_params = ListParams::default();
+        if !self.label_selector.is_empty() {
+            list_params = list_params.labels(&self.label_selector.to_string());
+        }
+        if !self.field_selector.is_empty() {
+            list_params = list_params.fields(&self.field_selector.to_string());
+        }
+        list_params
+    }
+
+    fn applied_filter(&self, maintainers: Vec<String>) -> AppliedFilter {
+        AppliedFilter {
+            clusters: self.clusters.clone(),
+            namespaces: self.namespaces.clone(),
+            all_namespaces: self.all_namespaces,
+            exclude_namespaces: self.exclude_namespaces.clone(),
+            maintainer_label_key: self.maintainer_label_key.clone(),
+            maintainers,
+            label_selector: Some(self.label_selector.to_string()).filter(|s| !s.is_empty()),
+            field_selector: Some(self.field_selector.to_string()).filter(|s| !s.is_empty()),
+            phases: self.phases.clone(),
+            exclude_terminating: self.exclude_terminating,
+            require_ready: self.require_ready,
+        }
+    }
+}
+
+async fn get_all_pods_info(
+    State(state): State<AppState>,
+    Query(format): Query<export::FormatParams>,
+    headers: HeaderMap,
+    Json(request_body): Json<PodComputeInfoRequestBody>,
+) -> Result<Response, ApiError> {
+    let query = build_pod_query(&state, &request_body).map_err(ApiError::bad_request)?;
+    let requested_page = cursor::Page::new(request_body.cursor.as_deref(), request_body.page_size)
+        .map_err(ApiError::bad_request)?;
+    let PodsInfo {
+        pods,
+        errors,
+        mut warnings,
+    } = get_pods_info(&state, &query).await?;
+    let (page, next_cursor) = cursor::paginate(pods, &requested_page);
+    warnings.extend(resource_warnings(&page));
+    if export::wants_csv(&format, &headers) {
+        return Ok(export::csv_response(&page, next_cursor, &errors, &warnings));
+    }
+    Ok(Json(PodComputeInfoResponse {
+        filter: query.applied_filter(request_body.maintainers.unwrap_or_default()),
+        cache_age_seconds: cache_age_seconds(&state, &query),
+        watch_healthy: watch_healthy(&state, &query),
+        units: query.units,
+        warnings,
+        errors,
+        next_cursor,
+        pods: page,
+    })
+    .into_response())
+}
+
+fn resource_warnings(pods: &[PodComputeInfo]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    for pod in pods {
+        for container in &pod.containers {
+            let resources = &container.compute_resources;
+            if !container.missing_requests.is_empty() {
+                warnings.push(format!(
+                    "container {} in pod {}/{} has no {} request",
+                    container.name,
+                    pod.namespace,
+                    pod.name,
+                    container.missing_requests.join(" or ")
+                ));
+            }
+            let unparsed = [
+                (&resources.requested_cpu, resources.requested_cpu_millicores),
+                (
+                    &resources.requested_memory,
+                    resources.requested_memory_bytes,
+                ),
+                (&resources.limit_cpu, resources.limit_cpu_millicores),
+                (&resources.limit_memory, resources.limit_memory_bytes),
+            ];
+            for (raw, normalized) in unparsed {
+                if let (Some(raw), None) = (raw, normalized) {
+                    warnings.push(format!(
+                        "container {} in pod {}/{} has an unparseable quantity {:?}",
+                        container.name, pod.namespace, pod.name, raw.0
+                    ));
+                }
+            }
+        }
+    }
+    warnings
+}
+
+fn build_pod_query(
+    state: &AppState,
+    request_body: &PodComputeInfoRequestBody,
+) -> anyhow::Result<PodQuery> {
+    let maintainers = request_body.maintainers.clone().unwrap_or_default();
+    let maintainer_label_key = &state.config.maintainer_label_key;
+    let mut label_selector = maintainer_selector(maintainer_label_key, &maintainers)?;
+    if let Some(selector) = &request_body.label_selector {
+        label_selector = label_selector.and(
+            selector
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid label_selector: {}", e))?,
+        );
+    }
+    let field_selector = match &request_body.field_selector {
+        Some(selector) => selector
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid field_selector: {}", e))?,
+        None => FieldSelector::default(),
+    };
+    let clusters = match &request_body.clusters {
+        Some(clusters) if !clusters.is_empty() => {
+            for name in clusters {
+                if !state.clusters.iter().any(|cluster| cluster.name == *name) {
+                    anyhow::bail!("unknown cluster {}", name);
+                }
+            }
+            clusters.clone()
+        }
+        _ => state
+            .clusters
+            .iter()
+            .map(|cluster| cluster.name.clone())
+            .collect(),
+    };
+    let requested_all = request_body.all_namespaces.unwrap_or(false);
+    let namespaces = if request_body.namespaces.is_empty() && !requested_all {
+        state.config.default_namespaces.clone()
+    } else {
+        request_body.namespaces.clone()
+    };
+    Ok(PodQuery {
+        clusters,
+        all_namespaces: requested_all || namespaces.is_empty(),
+        namespaces,
+        exclude_namespaces: request_body.exclude_namespaces.clone().unwrap_or_default(),
+        label_selector,
+        field_selector,
+        maintainer_label_key: maintainer_label_key.clone(),
+        units: DisplayUnits {
+            cpu: request_body.cpu_unit.unwrap_or_default(),
+            memory: request_body.memory_unit.unwrap_or_default(),
+        },
+        phases: request_body
+            .phases
+            .clone()
+            .unwrap_or_else(|| vec![PodPhase::Running]),
+        exclude_terminating: request_body.exclude_terminating.unwrap_or(false),
+        require_ready: request_body.require_ready.unwrap_or(false),
+        list_concurrency: state.config.list_concurrency,
+        usage_metrics: state.config.usage_metrics,
+        pricing: state.config.pricing.clone(),
+    })
+}
+
+// Paging only applies to the pods endpoint; the endpoints aggregating pods always cover every matching pod.
+fn reject_paging(request_body: &PodComputeInfoRequestBody) -> anyhow::Result<()> {
+    if request_body.page_size.is_some() || request_body.cursor.is_some() {
+        anyhow::bail!("page_size and cursor are only supported by /api/compute-info/pods");
+    }
+    Ok(())
+}
+
+// Builds a set-based selector so the apiserver only returns pods owned by the given maintainers.
+fn maintainer_selector(label_key: &str, maintainers: &[String]) -> anyhow::Result<LabelSelector> {
+    if maintainers.is_empty() {
+        return Ok(LabelSelector::default());
+    }
+    for maintainer in maintainers {
+        selector::validate_label_value(maintainer)
+            .map_err(|e| anyhow::anyhow!("invalid maintainer: {}", e))?;
+    }
+    Ok(LabelSelector {
+        requirements: vec![LabelRequirement::In(
+            label_key.to_string(),
+            maintainers.to_vec(),
+        )],
+    })
+}
+
+// Reports the stalest cache among the queried clusters.
+fn cache_age_seconds(state: &AppState, query: &PodQuery) -> Option<f64> {
+    query_clusters(state, query)
+        .filter_map(|cluster| cluster.pod_cache.as_ref())
+        .map(|cache| cache.age().as_secs_f64())
+        .reduce(f64::max)
+}
+
+// False as soon as one queried cluster's pod watch is failing.
+fn watch_healthy(state: &AppState, query: &PodQuery) -> Option<bool> {
+    query_clusters(state, query)
+        .filter_map(|cluster| cluster.pod_cache.as_ref())
+        .map(|cache| cache.watch_healthy())
+        .reduce(|a, b| a && b)
+}
+
+fn query_clusters<'a>(
+    state: &'a AppState,
+    query: &'a PodQuery,
+) -> impl Iterator<Item = &'a Cluster> {
+    state
+        .clusters
+        .iter()
+        .filter(|cluster| query.clusters.contains(&cluster.name))
+}
+
+struct PodsInfo {
+    pods: Vec<PodComputeInfo>,
+    errors: Vec<QueryError>,
+    warnings: Vec<String>,
+}
+
+// Pods are always returned ordered by cluster, namespace and name, so repeated queries and pages line up.
+// Clusters are queried concurrently and a failing cluster is reported in `errors` like a failing namespace.
+async fn get_pods_info(state: &AppState, query: &PodQuery) -> Result<PodsInfo, ApiError> {
+    let clusters: Vec<&Cluster> = query_clusters(state, query).collect();
+    let results = futures::future::join_all(
+        clusters
+            .iter()
+            .map(|cluster| cluster_pods_info(cluster, query)),
+    )
+    .await;
+    let mut pods_info = PodsInfo {
+        pods: Vec::new(),
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let mut failures = Vec::new();
+    for (cluster, result) in clusters.iter().zip(results) {
+        match result {
+            Ok(cluster_info) => {
+                pods_info.pods.extend(cluster_info.pods);
+                pods_info.errors.extend(cluster_info.errors);
+                pods_info.warnings.extend(cluster_info.warnings);
+            }
+            Err(e) => {
+                eprintln!("Error querying cluster {}: {}", cluster.name, e);
+                failures.push((cluster.name.clone(), e));
+            }
+        }
+    }
+    if !clusters.is_empty() && failures.len() == clusters.len() {
+        return Err(failures.remove(0).1);
+    }
+    pods_info
+        .errors
+        .extend(failures.into_iter().map(|(cluster, e)| QueryError {
+            cluster,
+            namespace: None,
+            status: e.status().as_u16(),
+            detail: e.to_string(),
+        }));
+    cursor::sort(&mut pods_info.pods);
+    Ok(pods_info)
+}
+
+async fn cluster_pods_info(cluster: &Cluster, query: &PodQuery) -> Result<PodsInfo, ApiError> {
+    let mut pods_info = match &cluster.pod_cache {
+        Some(cache) if !cache.is_ready() => {
+            return Err(ApiError::Unavailable(format!(
+                "pod cache of cluster {} is still warming up",
+                cluster.name
+            )))
+        }
+        Some(cache) => PodsInfo {
+            pods: cache
+                .pods()
+                .iter()
+                .filter(|pod| query.matches(pod))
+                .map(|pod| pod_compute_info(pod, &cluster.name, query))
+                .collect(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        },
+        None => list_pods_info(cluster, query).await?,
+    };
+    let warning = workloads::resolve_workloads(cluster, query, &mut pods_info.pods).await;
+    pods_info.warnings.extend(warning);
+    if query.usage_metrics {
+        let warning = usage::add_usage(cluster, query, &mut pods_info.pods).await;
+        pods_info.warnings.extend(warning);
+    }
+    if let Some(pricing) = &query.pricing {
+        let warning = cost::add_costs(cluster, pricing, &mut pods_info.pods).await;
+        pods_info.warnings.extend(warning);
+    }
+    Ok(pods_info)
+}
+
+// A failing namespace is reported in `errors` next to the pods of the others; only when every listing fails
+// does the whole query fail, with the status of the first error.
+async fn list_pods_info(cluster: &Cluster, query: &PodQuery) -> Result<PodsInfo, ApiError> {
+    let client = &cluster.client;
+    let list_params = query.list_params();
+    let scope = namespaces::resolve_scope(
+        client,
+        &query.namespaces,
+        query.all_namespaces,
+        &query.exclude_namespaces,
+    )
+    .await?;
+    let apis: Vec<(Option<String>, Api<Pod>)> = match scope {
+        NamespaceScope::All => vec![(None, Api::all(client.clone()))],
+        NamespaceScope::Namespaces(namespaces) => namespaces
+            .into_iter()
+            .map(|namespace| {
+                let api = Api::namespaced(client.clone(), &namespace);
+                (Some(namespace), api)
+            })
+            .collect(),
+    };
+    let listings = apis.len();
     let pods = Arc::new(Mutex::new(Vec::new()));
-    stream::iter(namespaces)
-        .for_each_concurrent(None, |namespace| {
-            let api: Api<Pod> = Api::namespaced(client.clone(), &namespace);
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    stream::iter(apis)
+        .for_each_concurrent(query.list_concurrency, |(namespace, api)| {
             let pods = Arc::clone(&pods);
+            let failures = Arc::clone(&failures);
+            let list_params = list_params.clone();
             async move {
-                let pods_in_namespace = api.list(&Default::default()).await;
-                match pods_in_namespace {
-                    Ok(p) => {
-                        for pod in p.items.into_iter().filter(|pod| {
-                            pod.status.as_ref().unwrap().phase.as_ref().unwrap() == "Running"
-                        }) {
-                            let labels = pod.metadata.labels.unwrap_or_default().clone();
-                            let maintainer =
-                                labels.get("maintainer").unwrap_or(&"".to_string()).clone();
-                            let node_name = pod
-                                .spec
-                                .as_ref()
-                                .unwrap()
-                                .node_name
-                                .clone()
-                                .unwrap_or("".to_string());
-                            let raw_containers = pod.spec.as_ref().unwrap().containers.clone();
-                            let containers: Vec<Container> = raw_containers
-                                .iter()
-                                .map(|container| Container {
-                                    name: container.name.clone(),
-                                    image: container.image.clone(),
-                                    compute_resources: ComputeResources {
-                                        requested_cpu: container
-                                            .resources
-                                            .as_ref()
-                                            .unwrap()
-                                            .requests
-                                            .as_ref()
-                                            .unwrap()
-                                            .get("cpu")
-                                            .unwrap()
-                                            .clone(),
-                                        requested_memory: container
-                                            .resources
-                                            .as_ref()
-                                            .unwrap()
-                                            .requests
-                                            .as_ref()
-                                            .unwrap()
-                                            .get("memory")
-                                            .unwrap()
-                                            .clone(),
-                                    },
-                                })
-                                .collect();
-
-                            let pod_compute_info = PodComputeInfo {
-                                name: pod.metadata.name.unwrap(),
-                                namespace: pod.metadata.namespace.unwrap(),
-                                node_name,
-                                maintainer: maintainer.to_string(),
-                                containers,
-                                metadata: Some(Metadata {
-                                    labels: Some(labels),
-                                }),
-                            };
-                            pods.lock().unwrap().push(pod_compute_info);
+                // Pages are converted as they arrive; UIDs only guard against duplicates after a restarted listing.
+                let mut seen = HashSet::new();
+                let mut pages = std::pin::pin!(pager::pod_pages(api, list_params));
+                while let Some(page) = pages.next().await {
+                    match page {
+                        Ok(items) => {
+                            for pod in items.iter().filter(|pod| {
+                                query.matches_lifecycle(pod)
+                                    && !namespaces::is_excluded(
+                                        pod.metadata.namespace.as_deref().unwrap_or_default(),
+                                        &query.exclude_namespaces,
+                                    )
+                                    && seen.insert(pod.metadata.uid.clone())
+                            }) {
+                                let pod_compute_info = pod_compute_info(pod, &cluster.name, query);
+                                pods.lock().unwrap().push(pod_compute_info);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!(
+                                "Error listing pods of cluster {} in {:?}: {}",
+                                cluster.name, namespace, e
+                            );
+                            failures.lock().unwrap().push((namespace, e));
+                            break;
                         }
                     }
-                    Err(e) => eprintln!("Error: {}", e),
                 }
             }
         })
         .await;
-    Ok(Arc::try_unwrap(pods).unwrap().into_inner().unwrap())
+    let pods = Arc::try_unwrap(pods).unwrap().into_inner().unwrap();
+    let mut failures = Arc::try_unwrap(failures).unwrap().into_inner().unwrap();
+    if listings > 0 && failures.len() == listings {
+        return Err(failures.remove(0).1.into());
+    }
+    let errors = failures
+        .into_iter()
+        .map(|(namespace, e)| QueryError {
+            cluster: cluster.name.clone(),
+            namespace,
+            status: error::kube_status(&e).as_u16(),
+            detail: e.to_string(),
+        })
+        .collect();
+    Ok(PodsInfo {
+        pods,
+        errors,
+        warnings: Vec::new(),
+    })
+}
+
+fn is_ready(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions
+                .iter()
+                .any(|condition| condition.type_ == "Ready" && condition.status == "True")
+        })
+}
+
+fn pod_compute_info(pod: &Pod, cluster: &str, query: &PodQuery) -> PodComputeInfo {
+    let phase = PodPhase::of(pod);
+    let ready = is_ready(pod);
+    let labels = pod.metadata.labels.clone().unwrap_or_default();
+    let maintainer = labels
+        .get(&query.maintainer_label_key)
+        .cloned()
+        .unwrap_or_default();
+    let spec = pod.spec.clone().unwrap_or_default();
+    let node_name = spec.node_name.clone().unwrap_or_default();
+    let app_containers = spec
+        .containers
+        .iter()
+        .map(|container| container_info(container, ContainerKind::App, query.units));
+    // Init containers with restartPolicy Always are native sidecars and keep running next to the app containers.
+    let init_containers = spec.init_containers.iter().flatten().map(|container| {
+        let kind = match container.restart_policy.as_deref() {
+            Some("Always") => ContainerKind::Sidecar,
+            _ => ContainerKind::Init,
+        };
+        container_info(container, kind, query.units)
+    });
+    let containers: Vec<Container> = app_containers.chain(init_containers).collect();
+
+    PodComputeInfo {
+        cluster: cluster.to_string(),
+        name: pod.metadata.name.clone().unwrap_or_default(),
+        namespace: pod.metadata.namespace.clone().unwrap_or_default(),
+        phase,
+        ready,
+        node_name,
+        maintainer,
+        workload: workloads::controller(pod),
+        totals: PodTotals::new(&containers, query.units),
+        effective_requests: EffectiveRequests::new(
+            &containers,
+            spec.overhead.as_ref(),
+            query.units,
+        ),
+        containers,
+        cost: None,
+        metadata: Some(Metadata {
+            labels: Some(labels),
+        }),
+    }
+}
+
+fn container_info(
+    container: &k8s_openapi::api::core::v1::Container,
+    kind: ContainerKind,
+    units: DisplayUnits,
+) -> Container {
+    let compute_resources = ComputeResources::new(container.resources.as_ref(), units);
+    Container {
+        name: container.name.clone(),
+        image: container.image.clone(),
+        kind,
+        missing_requests: compute_resources.missing_requests(),
+        compute_resources,
+        used_cpu: None,
+        used_memory: None,
+        used_cpu_millicores: None,
+        used_memory_bytes: None,
+        cpu_usage_request_ratio: None,
+        memory_usage_request_ratio: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Serves `router` on a local port and returns a client pointed at it, standing in for a cluster's apiserver.
+    pub async fn fake_apiserver(router: Router) -> kube::Client {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        kube::Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap()
+    }
+
+    pub fn app_state(clusters: Vec<Cluster>) -> AppState {
+        AppState {
+            clusters: Arc::new(clusters),
+            snapshots: None,
+            config: Arc::new(Config::default()),
+        }
+    }
+
+    // A running pod as the apiserver returns it.
+    pub fn pod_json(namespace: &str, name: &str) -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"name": name, "namespace": namespace, "uid": format!("{}/{}", namespace, name)},
+            "spec": {"containers": [{"name": "app", "resources": {"requests": {"cpu": "100m", "memory": "64Mi"}}}]},
+            "status": {"phase": "Running"},
+        })
+    }
+
+    pub fn pod_list(items: Vec<serde_json::Value>, continue_token: &str) -> serde_json::Value {
+        serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "PodList",
+            "metadata": {"resourceVersion": "1", "continue": continue_token},
+            "items": items,
+        })
+    }
+
+    pub fn pod_query() -> PodQuery {
+        PodQuery {
+            clusters: vec!["default".to_string()],
+            namespaces: Vec::new(),
+            all_namespaces: true,
+            exclude_namespaces: Vec::new(),
+            label_selector: LabelSelector::default(),
+            field_selector: FieldSelector::default(),
+            maintainer_label_key: "maintainer".to_string(),
+            units: DisplayUnits::default(),
+            phases: vec![PodPhase::Running],
+            exclude_terminating: false,
+            require_ready: false,
+            list_concurrency: 1,
+            usage_metrics: false,
+            pricing: None,
+        }
+    }
+
+    // A container requesting `cpu` and `memory`, in the shape the pod spec gives them.
+    pub fn spec_container(
+        name: &str,
+        cpu: &str,
+        memory: &str,
+    ) -> k8s_openapi::api::core::v1::Container {
+        k8s_openapi::api::core::v1::Container {
+            name: name.to_string(),
+            resources: Some(ResourceRequirements {
+                requests: Some(BTreeMap::from([
+                    ("cpu".to_string(), Quantity(cpu.to_string())),
+                    ("memory".to_string(), Quantity(memory.to_string())),
+                ])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    // A pod with the given containers, converted the way a query converts it.
+    pub fn pod_info(
+        cluster: &str,
+        namespace: &str,
+        name: &str,
+        containers: Vec<k8s_openapi::api::core::v1::Container>,
+    ) -> PodComputeInfo {
+        let pod = Pod {
+            metadata: kube::api::ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(namespace.to_string()),
+                ..Default::default()
+            },
+            spec: Some(k8s_openapi::api::core::v1::PodSpec {
+                containers,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        pod_compute_info(&pod, cluster, &pod_query())
+    }
+
+    fn container(kind: ContainerKind, cpu: &str) -> Container {
+        container_info(
+            &spec_container("c", cpu, "1Mi"),
+            kind,
+            DisplayUnits::default(),
+        )
+    }
+
+    fn effective_cpu(containers: &[Container]) -> u64 {
+        effective_request(containers, |r| r.requested_cpu_millicores)
+    }
+
+    #[test]
+    fn effective_request_of_app_containers_is_their_sum() {
+        let containers = [
+            container(ContainerKind::App, "100m"),
+            container(ContainerKind::App, "200m"),
+        ];
+        assert_eq!(effective_cpu(&containers), 300);
+        assert_eq!(effective_cpu(&[]), 0);
+    }
+
+    #[test]
+    fn effective_request_takes_a_larger_init_container() {
+        let containers = [
+            container(ContainerKind::App, "100m"),
+            container(ContainerKind::App, "200m"),
+            container(ContainerKind::Init, "500m"),
+            container(ContainerKind::Init, "250m"),
+        ];
+        assert_eq!(effective_cpu(&containers), 500);
+        let smaller_init = [
+            container(ContainerKind::App, "400m"),
+            container(ContainerKind::Init, "250m"),
+        ];
+        assert_eq!(effective_cpu(&smaller_init), 400);
+    }
+
+    #[test]
+    fn effective_request_counts_sidecars_started_before_an_init_container() {
+        // Init containers are in spec order: sidecar, init, sidecar.
+        let containers = [
+            container(ContainerKind::App, "200m"),
+            container(ContainerKind::Sidecar, "50m"),
+            container(ContainerKind::Init, "400m"),
+            container(ContainerKind::Sidecar, "100m"),
+        ];
+        // The init container runs next to the first sidecar only: 400m + 50m, above the running 350m.
+        assert_eq!(effective_cpu(&containers), 450);
+
+        let sidecar_after_init = [
+            container(ContainerKind::App, "200m"),
+            container(ContainerKind::Init, "400m"),
+            container(ContainerKind::Sidecar, "100m"),
+        ];
+        assert_eq!(effective_cpu(&sidecar_after_init), 400);
+
+        let sidecars_dominate = [
+            container(ContainerKind::App, "300m"),
+            container(ContainerKind::Sidecar, "100m"),
+            container(ContainerKind::Init, "350m"),
+            container(ContainerKind::Sidecar, "100m"),
+        ];
+        assert_eq!(effective_cpu(&sidecars_dominate), 500);
+    }
+
+    #[test]
+    fn effective_requests_add_overhead() {
+        let containers = [
+            container(ContainerKind::App, "100m"),
+            container(ContainerKind::Init, "300m"),
+        ];
+        let overhead = BTreeMap::from([
+            ("cpu".to_string(), Quantity("250m".to_string())),
+            ("memory".to_string(), Quantity("120Mi".to_string())),
+        ]);
+        let requests =
+            EffectiveRequests::new(&containers, Some(&overhead), DisplayUnits::default());
+        assert_eq!(requests.cpu_millicores, 550);
+        assert_eq!(requests.overhead_cpu_millicores, 250);
+        assert_eq!(requests.memory_bytes, 121 * 1024 * 1024);
+        assert_eq!(requests.overhead_memory_bytes, 120 * 1024 * 1024);
+    }
+
+    #[test]
+    fn pod_compute_info_classifies_native_sidecars() {
+        let mut sidecar = spec_container("proxy", "100m", "64Mi");
+        sidecar.restart_policy = Some("Always".to_string());
+        let pod = Pod {
+            spec: Some(k8s_openapi::api::core::v1::PodSpec {
+                containers: vec![spec_container("app", "200m", "128Mi")],
+                init_containers: Some(vec![sidecar, spec_container("migrate", "500m", "32Mi")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let info = pod_compute_info(&pod, "default", &pod_query());
+        let kinds: Vec<_> = info.containers.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ContainerKind::App,
+                ContainerKind::Sidecar,
+                ContainerKind::Init
+            ]
+        );
+        assert_eq!(info.effective_requests.cpu_millicores, 600);
+        assert_eq!(info.effective_requests.memory_bytes, 192 * 1024 * 1024);
+    }
+
+    // A container requesting `cpu` and `memory` and limited to one core and 1Gi.
+    fn limited_container(name: &str, cpu: &str, memory: &str) -> Container {
+        let mut container = spec_container(name, cpu, memory);
+        let resources = container.resources.as_mut().unwrap();
+        let limits = resources
+            .requests
+            .iter()
+            .flatten()
+            .map(|(resource, _)| {
+                let limit = if resource == "cpu" { "1" } else { "1Gi" };
+                (resource.clone(), Quantity(limit.to_string()))
+            })
+            .collect();
+        resources.limits = Some(limits);
+        container_info(&container, ContainerKind::App, DisplayUnits::default())
+    }
+
+    #[test]
+    fn pod_totals_bound_limits_only_when_every_container_sets_them() {
+        let containers = [
+            limited_container("app", "250m", "256Mi"),
+            limited_container("proxy", "250m", "256Mi"),
+            container(ContainerKind::Init, "2"),
+        ];
+        let totals = PodTotals::new(&containers, DisplayUnits::default());
+        assert_eq!(totals.requested_cpu_millicores, 500);
+        assert_eq!(totals.requested_memory_bytes, 512 * 1024 * 1024);
+        assert_eq!(totals.limit_cpu_millicores, Some(2000));
+        assert_eq!(totals.limit_memory_bytes, Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(totals.cpu_request_limit_ratio, Some(0.25));
+        assert_eq!(totals.memory_request_limit_ratio, Some(0.25));
+        assert_eq!(totals.display.limit_cpu, Some(2.0));
+
+        let containers = [
+            limited_container("app", "250m", "256Mi"),
+            container(ContainerKind::Sidecar, "100m"),
+        ];
+        let totals = PodTotals::new(&containers, DisplayUnits::default());
+        assert_eq!(totals.requested_cpu_millicores, 350);
+        assert_eq!(totals.limit_cpu_millicores, None);
+        assert_eq!(totals.limit_memory_bytes, None);
+        assert_eq!(totals.cpu_request_limit_ratio, None);
+        assert_eq!(totals.memory_request_limit_ratio, None);
+        assert_eq!(totals.display.limit_cpu, None);
+    }
+
+    #[test]
+    fn containers_without_requests_are_reported() {
+        let best_effort = k8s_openapi::api::core::v1::Container {
+            name: "best-effort".to_string(),
+            ..Default::default()
+        };
+        let memory_only = k8s_openapi::api::core::v1::Container {
+            name: "memory-only".to_string(),
+            resources: Some(ResourceRequirements {
+                requests: Some(BTreeMap::from([(
+                    "memory".to_string(),
+                    Quantity("64Mi".to_string()),
+                )])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let pod = pod_info("default", "shop", "web", vec![best_effort, memory_only]);
+
+        let resources = &pod.containers[0].compute_resources;
+        assert_eq!(resources.requested_cpu, None);
+        assert_eq!(resources.requested_memory, None);
+        assert_eq!(resources.requested_cpu_millicores, None);
+        assert_eq!(resources.requested_memory_bytes, None);
+        assert_eq!(resources.limit_cpu_millicores, None);
+        assert_eq!(resources.cpu_request_limit_ratio, None);
+        assert_eq!(pod.containers[0].missing_requests, ["cpu", "memory"]);
+
+        let resources = &pod.containers[1].compute_resources;
+        assert_eq!(resources.requested_cpu_millicores, None);
+        assert_eq!(resources.requested_memory_bytes, Some(64 * 1024 * 1024));
+        assert_eq!(resources.display.requested_cpu, None);
+        assert_eq!(pod.containers[1].missing_requests, ["cpu"]);
+
+        assert_eq!(
+            resource_warnings(&[pod]),
+            [
+                "container best-effort in pod shop/web has no cpu or memory request",
+                "container memory-only in pod shop/web has no cpu request",
+            ]
+        );
+    }
+
+    async fn listing_cluster(name: &str, router: Router) -> Cluster {
+        Cluster {
+            name: name.to_string(),
+            client: fake_apiserver(router).await,
+            pod_cache: None,
+        }
+    }
+
+    fn pods_router() -> Router {
+        Router::new().route(
+            "/api/v1/pods",
+            get(|| async { Json(pod_list(vec![pod_json("shop", "web")], "")) }),
+        )
+    }
+
+    fn query_of(clusters: &[&str]) -> PodQuery {
+        PodQuery {
+            clusters: clusters.iter().map(|cluster| cluster.to_string()).collect(),
+            ..pod_query()
+        }
+    }
+
+    fn names(pods: &[PodComputeInfo]) -> Vec<String> {
+        pods.iter()
+            .map(|pod| format!("{}/{}/{}", pod.cluster, pod.namespace, pod.name))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_failing_cluster_is_reported_next_to_the_pods_of_the_others() {
+        let state = app_state(vec![
+            listing_cluster("prod", pods_router()).await,
+            listing_cluster("staging", Router::new()).await,
+        ]);
+        let pods_info = get_pods_info(&state, &query_of(&["prod", "staging"]))
+            .await
+            .unwrap();
+        assert_eq!(names(&pods_info.pods), ["prod/shop/web"]);
+        assert_eq!(pods_info.errors.len(), 1);
+        let error = &pods_info.errors[0];
+        assert_eq!(error.cluster, "staging");
+        assert_eq!(error.namespace, None);
+        assert_eq!(error.status, 404);
+    }
+
+    #[tokio::test]
+    async fn the_query_fails_when_every_cluster_fails() {
+        let state = app_state(vec![
+            listing_cluster("prod", Router::new()).await,
+            listing_cluster("staging", Router::new()).await,
+        ]);
+        let Err(e) = get_pods_info(&state, &query_of(&["prod", "staging"])).await else {
+            panic!("the query succeeded");
+        };
+        assert_eq!(e.status(), axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn a_warming_pod_cache_answers_unavailable() {
+        let client = fake_apiserver(Router::new()).await;
+        let warming = Cluster {
+            name: "staging".to_string(),
+            pod_cache: Some(cache::PodCache::start(client.clone(), "staging")),
+            client,
+        };
+        let state = app_state(vec![listing_cluster("prod", pods_router()).await, warming]);
+
+        let Err(e) = get_pods_info(&state, &query_of(&["staging"])).await else {
+            panic!("the query succeeded");
+        };
+        assert_eq!(e.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert!(e.to_string().contains("still warming up"));
+
+        let pods_info = get_pods_info(&state, &query_of(&["prod", "staging"]))
+            .await
+            .unwrap();
+        assert_eq!(names(&pods_info.pods), ["prod/shop/web"]);
+        assert_eq!(pods_info.errors[0].cluster, "staging");
+        assert_eq!(pods_info.errors[0].status, 503);
+    }
+
+    #[test]
+    fn reject_paging_only_accepts_bodies_without_page_size_or_cursor() {
+        assert!(reject_paging(&PodComputeInfoRequestBody::default()).is_ok());
+        let paged = PodComputeInfoRequestBody {
+            page_size: Some(10),
+            ..Default::default()
+        };
+        assert!(reject_paging(&paged).is_err());
+        let with_cursor = PodComputeInfoRequestBody {
+            cursor: Some("00".to_string()),
+            ..Default::default()
+        };
+        assert!(reject_paging(&with_cursor).is_err());
+    }
+
+    // A pod in `phase` with the given pod conditions.
+    fn lifecycle_pod(phase: &str, conditions: serde_json::Value) -> Pod {
+        let mut pod = pod_json("shop", "web");
+        pod["status"] = serde_json::json!({"phase": phase, "conditions": conditions});
+        serde_json::from_value(pod).unwrap()
+    }
+
+    fn ready_pod(ready: &str) -> Pod {
+        lifecycle_pod(
+            "Running",
+            serde_json::json!([{"type": "Ready", "status": ready}]),
+        )
+    }
+
+    #[test]
+    fn build_pod_query_selects_running_pods_by_default() {
+        let state = app_state(Vec::new());
+        let query = build_pod_query(&state, &PodComputeInfoRequestBody::default()).unwrap();
+        assert_eq!(query.phases, [PodPhase::Running]);
+        assert!(!query.exclude_terminating);
+        assert!(!query.require_ready);
+    }
+
+    #[test]
+    fn matches_lifecycle_selects_phases() {
+        let phases = [
+            "Pending",
+            "Running",
+            "Succeeded",
+            "Failed",
+            "Unknown",
+            "Evicted",
+        ];
+        let matching = |query: &PodQuery| -> Vec<&str> {
+            phases
+                .into_iter()
+                .filter(|phase| {
+                    query.matches_lifecycle(&lifecycle_pod(phase, serde_json::json!([])))
+                })
+                .collect()
+        };
+        assert_eq!(matching(&pod_query()), ["Running"]);
+        let query = PodQuery {
+            phases: vec![PodPhase::Pending, PodPhase::Failed, PodPhase::Unknown],
+            ..pod_query()
+        };
+        // Phases Kubernetes does not define are treated as Unknown.
+        assert_eq!(
+            matching(&query),
+            ["Pending", "Failed", "Unknown", "Evicted"]
+        );
+    }
+
+    #[test]
+    fn matches_lifecycle_can_exclude_terminating_pods() {
+        let mut terminating = ready_pod("True");
+        terminating.metadata.deletion_timestamp = Some(
+            k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(chrono::Utc::now()),
+        );
+        assert!(pod_query().matches_lifecycle(&terminating));
+        let query = PodQuery {
+            exclude_terminating: true,
+            ..pod_query()
+        };
+        assert!(!query.matches_lifecycle(&terminating));
+        assert!(query.matches_lifecycle(&ready_pod("True")));
+    }
+
+    #[test]
+    fn matches_lifecycle_can_require_ready_pods() {
+        assert!(pod_query().matches_lifecycle(&ready_pod("False")));
+        let query = PodQuery {
+            require_ready: true,
+            ..pod_query()
+        };
+        assert!(query.matches_lifecycle(&ready_pod("True")));
+        assert!(!query.matches_lifecycle(&ready_pod("False")));
+        assert!(!query.matches_lifecycle(&lifecycle_pod("Running", serde_json::json!([]))));
+    }
+
+    #[test]
+    fn is_ready_needs_a_true_ready_condition() {
+        assert!(is_ready(&ready_pod("True")));
+        assert!(!is_ready(&ready_pod("False")));
+        assert!(!is_ready(&ready_pod("Unknown")));
+        assert!(!is_ready(&lifecycle_pod(
+            "Running",
+            serde_json::json!([{"type": "ContainersReady", "status": "True"}]),
+        )));
+        let mut without_status = ready_pod("True");
+        without_status.status = None;
+        assert!(!is_ready(&without_status));
+    }
+
+    #[test]
+    fn maintainer_selector_builds_a_set_requirement() {
+        let selector =
+            maintainer_selector("maintainer", &["team-a".to_string(), "team-b".to_string()])
+                .unwrap();
+        assert_eq!(selector.to_string(), "maintainer in (team-a,team-b)");
+        assert!(maintainer_selector("maintainer", &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn maintainer_selector_rejects_values_that_change_the_selector() {
+        for maintainer in ["a),x in (b", "a,b", "team a", "a!=b", "-team"] {
+            assert!(
+                maintainer_selector("maintainer", &[maintainer.to_string()]).is_err(),
+                "{:?} was accepted",
+                maintainer
+            );
+        }
+    }
 }
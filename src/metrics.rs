@@ -0,0 +1,192 @@
+use std::fmt::Write;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+
+use crate::error::{ApiError, QueryError};
+use crate::{
+    build_pod_query, get_pods_info, AppState, ComputeResources, PodComputeInfo,
+    PodComputeInfoRequestBody, PodsInfo,
+};
+
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: fn(&ComputeResources) -> Option<f64>,
+}
+
+const GAUGES: &[Gauge] = &[
+    Gauge {
+        name: "workload_requested_cpu_cores",
+        help: "CPU requested by the container, in cores.",
+        value: |r| r.requested_cpu_millicores.map(|m| m as f64 / 1000.0),
+    },
+    Gauge {
+        name: "workload_requested_memory_bytes",
+        help: "Memory requested by the container, in bytes.",
+        value: |r| r.requested_memory_bytes.map(|b| b as f64),
+    },
+    Gauge {
+        name: "workload_limit_cpu_cores",
+        help: "CPU limit of the container, in cores.",
+        value: |r| r.limit_cpu_millicores.map(|m| m as f64 / 1000.0),
+    },
+    Gauge {
+        name: "workload_limit_memory_bytes",
+        help: "Memory limit of the container, in bytes.",
+        value: |r| r.limit_memory_bytes.map(|b| b as f64),
+    },
+];
+
+// Publishes the running pods of every cluster in all namespaces, whatever `default_namespaces` says. A cluster that
+// cannot be read is reported through `workload_cluster_up` instead of failing the scrape.
+pub async fn get_metrics(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let request_body = PodComputeInfoRequestBody {
+        all_namespaces: Some(true),
+        ..Default::default()
+    };
+    let mut query = build_pod_query(&state, &request_body)?;
+    query.usage_metrics = false;
+    query.pricing = None;
+    let PodsInfo { pods, errors, .. } = match get_pods_info(&state, &query).await {
+        Ok(pods_info) => pods_info,
+        Err(e) => PodsInfo {
+            pods: Vec::new(),
+            errors: query
+                .clusters
+                .iter()
+                .map(|cluster| QueryError {
+                    cluster: cluster.clone(),
+                    namespace: None,
+                    status: e.status().as_u16(),
+                    detail: e.to_string(),
+                })
+                .collect(),
+            warnings: Vec::new(),
+        },
+    };
+    Ok((
+        [(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)],
+        render(&pods, &query.clusters, &errors),
+    )
+        .into_response())
+}
+
+pub fn render(pods: &[PodComputeInfo], clusters: &[String], errors: &[QueryError]) -> String {
+    let mut out = String::new();
+    for gauge in GAUGES {
+        let _ = writeln!(out, "# HELP {} {}", gauge.name, gauge.help);
+        let _ = writeln!(out, "# TYPE {} gauge", gauge.name);
+        for pod in pods {
+            for container in &pod.containers {
+                if let Some(value) = (gauge.value)(&container.compute_resources) {
+                    let _ = writeln!(
+                        out,
+                        "{}{{cluster=\"{}\",namespace=\"{}\",pod=\"{}\",container=\"{}\",maintainer=\"{}\",node=\"{}\"}} {}",
+                        gauge.name,
+                        escape(&pod.cluster),
+                        escape(&pod.namespace),
+                        escape(&pod.name),
+                        escape(&container.name),
+                        escape(&pod.maintainer),
+                        escape(&pod.node_name),
+                        value
+                    );
+                }
+            }
+        }
+    }
+    let _ = writeln!(
+        out,
+        "# HELP workload_cluster_up Whether the pods of the cluster could be read (1) or not (0)."
+    );
+    let _ = writeln!(out, "# TYPE workload_cluster_up gauge");
+    for cluster in clusters {
+        let down = errors
+            .iter()
+            .any(|error| error.cluster == *cluster && error.namespace.is_none());
+        let _ = writeln!(
+            out,
+            "workload_cluster_up{{cluster=\"{}\"}} {}",
+            escape(cluster),
+            u8::from(!down)
+        );
+    }
+    let _ = writeln!(
+        out,
+        "# HELP workload_query_errors Pod listings of the cluster that failed in this scrape."
+    );
+    let _ = writeln!(out, "# TYPE workload_query_errors gauge");
+    for cluster in clusters {
+        let failed = errors
+            .iter()
+            .filter(|error| error.cluster == *cluster)
+            .count();
+        let _ = writeln!(
+            out,
+            "workload_query_errors{{cluster=\"{}\"}} {}",
+            escape(cluster),
+            failed
+        );
+    }
+    out
+}
+
+fn escape(label_value: &str) -> String {
+    label_value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod() -> PodComputeInfo {
+        crate::tests::pod_info(
+            "prod",
+            "shop",
+            "web",
+            vec![crate::tests::spec_container("app", "250m", "64Mi")],
+        )
+    }
+
+    #[test]
+    fn renders_container_gauges() {
+        let out = render(&[pod()], &["prod".to_string()], &[]);
+        assert!(out.contains(
+            "workload_requested_cpu_cores{cluster=\"prod\",namespace=\"shop\",pod=\"web\",container=\"app\",maintainer=\"\",node=\"\"} 0.25\n"
+        ));
+        assert!(out.contains("workload_requested_memory_bytes{cluster=\"prod\","));
+        // Unset limits are left out rather than published as zero.
+        assert!(!out.contains("workload_limit_cpu_cores{"));
+        assert!(out.contains("workload_cluster_up{cluster=\"prod\"} 1\n"));
+        assert!(out.contains("workload_query_errors{cluster=\"prod\"} 0\n"));
+    }
+
+    #[test]
+    fn reports_failing_clusters_and_listings() {
+        let error = |cluster: &str, namespace: Option<&str>| QueryError {
+            cluster: cluster.to_string(),
+            namespace: namespace.map(str::to_string),
+            status: 503,
+            detail: "unreachable".to_string(),
+        };
+        let clusters = ["prod".to_string(), "staging".to_string()];
+        let errors = [error("staging", None), error("prod", Some("shop"))];
+        let out = render(&[], &clusters, &errors);
+        assert!(out.contains("workload_cluster_up{cluster=\"prod\"} 1\n"));
+        assert!(out.contains("workload_cluster_up{cluster=\"staging\"} 0\n"));
+        assert!(out.contains("workload_query_errors{cluster=\"prod\"} 1\n"));
+        assert!(out.contains("workload_query_errors{cluster=\"staging\"} 1\n"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -0,0 +1,182 @@
+use k8s_openapi::api::core::v1::Namespace;
+use kube::{Api, Client};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NamespaceScope {
+    All,
+    Namespaces(Vec<String>),
+}
+
+pub fn is_pattern(namespace: &str) -> bool {
+    namespace.contains(['*', '?'])
+}
+
+// Shell-style matching where `*` matches any run of characters and `?` exactly one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+pub fn in_scope(
+    namespace: &str,
+    namespaces: &[String],
+    all_namespaces: bool,
+    exclude_patterns: &[String],
+) -> bool {
+    let included = all_namespaces
+        || namespaces
+            .iter()
+            .any(|pattern| glob_match(pattern, namespace));
+    included && !is_excluded(namespace, exclude_patterns)
+}
+
+pub fn is_excluded(namespace: &str, exclude_patterns: &[String]) -> bool {
+    exclude_patterns
+        .iter()
+        .any(|pattern| glob_match(pattern, namespace))
+}
+
+pub async fn resolve_scope(
+    client: &Client,
+    namespaces: &[String],
+    all_namespaces: bool,
+    exclude_patterns: &[String],
+) -> anyhow::Result<NamespaceScope> {
+    if all_namespaces || namespaces.is_empty() {
+        return Ok(NamespaceScope::All);
+    }
+    let mut resolved: Vec<String> = namespaces
+        .iter()
+        .filter(|namespace| !is_pattern(namespace))
+        .cloned()
+        .collect();
+    let patterns: Vec<&String> = namespaces.iter().filter(|ns| is_pattern(ns)).collect();
+    if !patterns.is_empty() {
+        let api: Api<Namespace> = Api::all(client.clone());
+        for namespace in api.list(&Default::default()).await? {
+            let name = namespace.metadata.name.unwrap_or_default();
+            if patterns.iter().any(|pattern| glob_match(pattern, &name)) {
+                resolved.push(name);
+            }
+        }
+    }
+    resolved.sort();
+    resolved.dedup();
+    resolved.retain(|namespace| !is_excluded(namespace, exclude_patterns));
+    Ok(NamespaceScope::Namespaces(resolved))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn glob_matches_literals_and_wildcards() {
+        assert!(glob_match("shop", "shop"));
+        assert!(!glob_match("shop", "shops"));
+        assert!(glob_match("team-*", "team-a"));
+        assert!(glob_match("team-*", "team-"));
+        assert!(!glob_match("team-*", "teams"));
+        assert!(glob_match("?-prod", "a-prod"));
+        assert!(!glob_match("?-prod", "ab-prod"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("**", "anything"));
+        assert!(!glob_match("", "shop"));
+    }
+
+    #[test]
+    fn glob_backtracks_over_stars() {
+        assert!(glob_match("*-prod", "team-a-prod"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(glob_match("*ab", "aaab"));
+        assert!(glob_match("kube-*-system", "kube-node-lease-system"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(!glob_match("*-prod", "team-a-prod-eu"));
+    }
+
+    #[test]
+    fn exclusions_take_precedence_over_inclusions() {
+        let namespaces = strings(&["team-*", "shop"]);
+        let excluded = strings(&["team-legacy*", "kube-*"]);
+        assert!(in_scope("team-a", &namespaces, false, &excluded));
+        assert!(in_scope("shop", &namespaces, false, &excluded));
+        assert!(!in_scope("team-legacy-1", &namespaces, false, &excluded));
+        assert!(!in_scope("other", &namespaces, false, &excluded));
+        assert!(in_scope("other", &[], true, &excluded));
+        assert!(!in_scope("kube-system", &[], true, &excluded));
+        assert!(!in_scope("shop", &namespaces, false, &strings(&["shop"])));
+    }
+
+    #[tokio::test]
+    async fn resolves_patterns_against_the_cluster_namespaces() {
+        let router = Router::new().route(
+            "/api/v1/namespaces",
+            get(|| async {
+                let items: Vec<_> = ["default", "shop", "team-a", "team-b", "team-legacy"]
+                    .iter()
+                    .map(|name| json!({"apiVersion": "v1", "kind": "Namespace", "metadata": {"name": name}}))
+                    .collect();
+                Json(json!({
+                    "apiVersion": "v1",
+                    "kind": "NamespaceList",
+                    "metadata": {},
+                    "items": items,
+                }))
+            }),
+        );
+        let client = crate::tests::fake_apiserver(router).await;
+        let scope = resolve_scope(
+            &client,
+            &strings(&["team-*", "shop", "team-a", "missing"]),
+            false,
+            &strings(&["*-legacy"]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            scope,
+            NamespaceScope::Namespaces(strings(&["missing", "shop", "team-a", "team-b"]))
+        );
+    }
+
+    #[tokio::test]
+    async fn resolves_without_listing_when_no_patterns_are_given() {
+        // The fake apiserver has no routes, so listing namespaces would fail the test.
+        let client = crate::tests::fake_apiserver(Router::new()).await;
+        let scope = resolve_scope(&client, &strings(&["b", "a", "b"]), false, &strings(&["a"]))
+            .await
+            .unwrap();
+        assert_eq!(scope, NamespaceScope::Namespaces(strings(&["b"])));
+        let scope = resolve_scope(&client, &strings(&["team-*"]), true, &[])
+            .await
+            .unwrap();
+        assert_eq!(scope, NamespaceScope::All);
+        let scope = resolve_scope(&client, &[], false, &[]).await.unwrap();
+        assert_eq!(scope, NamespaceScope::All);
+    }
+}
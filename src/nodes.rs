@@ -0,0 +1,395 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::extract::{Json, State};
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::ListParams;
+use kube::Api;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::cluster::Cluster;
+use crate::error::{self, ApiError, QueryError};
+use crate::quantity::{self, CpuUnit, DisplayUnits, MemoryUnit};
+use crate::selector::LabelSelector;
+use crate::{
+    build_pod_query, cache_age_seconds, get_pods_info, query_clusters, watch_healthy, AppState,
+    ContainerKind, PodComputeInfo, PodComputeInfoRequestBody, PodPhase, PodsInfo,
+};
+
+pub const INSTANCE_TYPE_LABEL: &str = "node.kubernetes.io/instance-type";
+
+#[derive(Debug, Deserialize, Default, JsonSchema)]
+pub struct NodesRequestBody {
+    clusters: Option<Vec<String>>,
+    label_selector: Option<String>,
+    cpu_unit: Option<CpuUnit>,
+    memory_unit: Option<MemoryUnit>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NodesResponse {
+    filter: NodesFilter,
+    cache_age_seconds: Option<f64>,
+    watch_healthy: Option<bool>,
+    units: DisplayUnits,
+    errors: Vec<QueryError>,
+    nodes: Vec<NodeInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct NodesFilter {
+    clusters: Vec<String>,
+    label_selector: Option<String>,
+}
+
+// Requests follow the scheduler (effective requests including overhead); limits follow `kubectl describe node` and
+// only add up the containers that set one, so an overcommitted node can exceed 100%.
+#[derive(Debug, Serialize, Clone, Default, JsonSchema)]
+pub struct NodeInfo {
+    cluster: String,
+    name: String,
+    instance_type: Option<String>,
+    unschedulable: bool,
+    pod_count: usize,
+    capacity_cpu_millicores: Option<u64>,
+    capacity_memory_bytes: Option<u64>,
+    allocatable_cpu_millicores: Option<u64>,
+    allocatable_memory_bytes: Option<u64>,
+    requested_cpu_millicores: u64,
+    requested_memory_bytes: u64,
+    limit_cpu_millicores: u64,
+    limit_memory_bytes: u64,
+    requested_cpu_percent: Option<f64>,
+    requested_memory_percent: Option<f64>,
+    limit_cpu_percent: Option<f64>,
+    limit_memory_percent: Option<f64>,
+    display: NodeDisplay,
+}
+
+#[derive(Debug, Serialize, Clone, Default, JsonSchema)]
+pub struct NodeDisplay {
+    capacity_cpu: Option<f64>,
+    capacity_memory: Option<f64>,
+    allocatable_cpu: Option<f64>,
+    allocatable_memory: Option<f64>,
+    requested_cpu: f64,
+    requested_memory: f64,
+    limit_cpu: f64,
+    limit_memory: f64,
+}
+
+pub async fn get_nodes(
+    State(state): State<AppState>,
+    Json(request_body): Json<NodesRequestBody>,
+) -> Result<Json<NodesResponse>, ApiError> {
+    let label_selector: LabelSelector = match &request_body.label_selector {
+        Some(selector) => selector
+            .parse()
+            .map_err(|e| ApiError::bad_request(format!("invalid label_selector: {}", e)))?,
+        None => LabelSelector::default(),
+    };
+    // Every pod holding resources on a node counts, whatever its namespace or readiness. Pods in the Unknown phase,
+    // typically on a node that lost contact, keep their requests reserved on it.
+    let pods_request = PodComputeInfoRequestBody {
+        clusters: request_body.clusters.clone(),
+        all_namespaces: Some(true),
+        cpu_unit: request_body.cpu_unit,
+        memory_unit: request_body.memory_unit,
+        phases: Some(vec![
+            PodPhase::Pending,
+            PodPhase::Running,
+            PodPhase::Unknown,
+        ]),
+        ..Default::default()
+    };
+    let mut query = build_pod_query(&state, &pods_request).map_err(ApiError::bad_request)?;
+    query.usage_metrics = false;
+    query.pricing = None;
+    let clusters: Vec<&Cluster> = query_clusters(&state, &query).collect();
+    let mut list_params = ListParams::default();
+    if !label_selector.is_empty() {
+        list_params = list_params.labels(&label_selector.to_string());
+    }
+
+    let (node_lists, pods_info) = futures::future::join(
+        futures::future::join_all(
+            clusters
+                .iter()
+                .map(|cluster| list_nodes(cluster, &list_params)),
+        ),
+        get_pods_info(&state, &query),
+    )
+    .await;
+    let PodsInfo {
+        pods, mut errors, ..
+    } = pods_info?;
+
+    let mut nodes = Vec::new();
+    let mut failures = Vec::new();
+    for (cluster, node_list) in clusters.iter().zip(node_lists) {
+        match node_list {
+            Ok(node_list) => {
+                nodes.extend(node_list.iter().map(|node| node_info(&cluster.name, node)))
+            }
+            Err(e) => {
+                eprintln!("Error listing nodes of cluster {}: {}", cluster.name, e);
+                failures.push((cluster.name.clone(), e));
+            }
+        }
+    }
+    if !clusters.is_empty() && failures.len() == clusters.len() {
+        return Err(failures.remove(0).1.into());
+    }
+    errors.extend(failures.into_iter().map(|(cluster, e)| QueryError {
+        cluster,
+        namespace: None,
+        status: error::kube_status(&e).as_u16(),
+        detail: e.to_string(),
+    }));
+    add_pods(&mut nodes, &pods, query.units);
+
+    Ok(Json(NodesResponse {
+        filter: NodesFilter {
+            clusters: query.clusters.clone(),
+            label_selector: Some(label_selector.to_string()).filter(|s| !s.is_empty()),
+        },
+        cache_age_seconds: cache_age_seconds(&state, &query),
+        watch_healthy: watch_healthy(&state, &query),
+        units: query.units,
+        errors,
+        nodes,
+    }))
+}
+
+async fn list_nodes(cluster: &Cluster, list_params: &ListParams) -> kube::Result<Vec<Node>> {
+    let api: Api<Node> = Api::all(cluster.client.clone());
+    Ok(api.list(list_params).await?.items)
+}
+
+fn node_info(cluster: &str, node: &Node) -> NodeInfo {
+    let status = node.status.clone().unwrap_or_default();
+    let capacity = status.capacity.unwrap_or_default();
+    let allocatable = status.allocatable.unwrap_or_default();
+    let cpu = |resources: &BTreeMap<String, Quantity>| {
+        resources
+            .get("cpu")
+            .and_then(|q| quantity::cpu_millicores(q).ok())
+    };
+    let memory = |resources: &BTreeMap<String, Quantity>| {
+        resources
+            .get("memory")
+            .and_then(|q| quantity::memory_bytes(q).ok())
+    };
+    NodeInfo {
+        cluster: cluster.to_string(),
+        name: node.metadata.name.clone().unwrap_or_default(),
+        instance_type: node
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(INSTANCE_TYPE_LABEL))
+            .cloned(),
+        unschedulable: node
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.unschedulable)
+            .unwrap_or(false),
+        capacity_cpu_millicores: cpu(&capacity),
+        capacity_memory_bytes: memory(&capacity),
+        allocatable_cpu_millicores: cpu(&allocatable),
+        allocatable_memory_bytes: memory(&allocatable),
+        ..Default::default()
+    }
+}
+
+fn add_pods(nodes: &mut [NodeInfo], pods: &[PodComputeInfo], units: DisplayUnits) {
+    let mut by_node: HashMap<(&str, &str), Vec<&PodComputeInfo>> = HashMap::new();
+    for pod in pods.iter().filter(|pod| !pod.node_name.is_empty()) {
+        by_node
+            .entry((pod.cluster.as_str(), pod.node_name.as_str()))
+            .or_default()
+            .push(pod);
+    }
+    for node in nodes {
+        let node_pods = by_node
+            .remove(&(node.cluster.as_str(), node.name.as_str()))
+            .unwrap_or_default();
+        node.pod_count = node_pods.len();
+        for pod in node_pods {
+            node.requested_cpu_millicores += pod.effective_requests.cpu_millicores;
+            node.requested_memory_bytes += pod.effective_requests.memory_bytes;
+            for container in pod
+                .containers
+                .iter()
+                .filter(|container| container.kind != ContainerKind::Init)
+            {
+                let resources = &container.compute_resources;
+                node.limit_cpu_millicores += resources.limit_cpu_millicores.unwrap_or(0);
+                node.limit_memory_bytes += resources.limit_memory_bytes.unwrap_or(0);
+            }
+        }
+        node.requested_cpu_percent = percent(
+            node.requested_cpu_millicores,
+            node.allocatable_cpu_millicores,
+        );
+        node.requested_memory_percent =
+            percent(node.requested_memory_bytes, node.allocatable_memory_bytes);
+        node.limit_cpu_percent =
+            percent(node.limit_cpu_millicores, node.allocatable_cpu_millicores);
+        node.limit_memory_percent = percent(node.limit_memory_bytes, node.allocatable_memory_bytes);
+        node.display = NodeDisplay {
+            capacity_cpu: node
+                .capacity_cpu_millicores
+                .map(|m| units.cpu.convert_millicores(m)),
+            capacity_memory: node
+                .capacity_memory_bytes
+                .map(|b| units.memory.convert_bytes(b)),
+            allocatable_cpu: node
+                .allocatable_cpu_millicores
+                .map(|m| units.cpu.convert_millicores(m)),
+            allocatable_memory: node
+                .allocatable_memory_bytes
+                .map(|b| units.memory.convert_bytes(b)),
+            requested_cpu: units.cpu.convert_millicores(node.requested_cpu_millicores),
+            requested_memory: units.memory.convert_bytes(node.requested_memory_bytes),
+            limit_cpu: units.cpu.convert_millicores(node.limit_cpu_millicores),
+            limit_memory: units.memory.convert_bytes(node.limit_memory_bytes),
+        };
+    }
+}
+
+fn percent(used: u64, allocatable: Option<u64>) -> Option<f64> {
+    match allocatable {
+        Some(allocatable) if allocatable > 0 => Some(used as f64 * 100.0 / allocatable as f64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{NodeSpec, NodeStatus, Pod, PodSpec};
+
+    fn quantities(cpu: &str, memory: &str) -> BTreeMap<String, Quantity> {
+        BTreeMap::from([
+            ("cpu".to_string(), Quantity(cpu.to_string())),
+            ("memory".to_string(), Quantity(memory.to_string())),
+        ])
+    }
+
+    fn node(name: &str, allocatable: Option<BTreeMap<String, Quantity>>) -> NodeInfo {
+        let node = Node {
+            metadata: kube::api::ObjectMeta {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            status: Some(NodeStatus {
+                allocatable,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        node_info("prod", &node)
+    }
+
+    // A pod on `node` whose app container requests 100m and is limited to 200m CPU, next to a 500m init container.
+    fn pod(cluster: &str, node: &str) -> PodComputeInfo {
+        let mut app = crate::tests::spec_container("app", "100m", "64Mi");
+        app.resources.as_mut().unwrap().limits = Some(quantities("200m", "128Mi"));
+        let mut init = crate::tests::spec_container("init", "500m", "32Mi");
+        init.resources.as_mut().unwrap().limits = Some(quantities("1", "1Gi"));
+        let pod = Pod {
+            spec: Some(PodSpec {
+                node_name: Some(node.to_string()).filter(|node| !node.is_empty()),
+                containers: vec![app],
+                init_containers: Some(vec![init]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        crate::pod_compute_info(&pod, cluster, &crate::tests::pod_query())
+    }
+
+    fn unlimited_pod(node: &str) -> PodComputeInfo {
+        let mut pod = crate::tests::pod_info(
+            "prod",
+            "shop",
+            "unlimited",
+            vec![crate::tests::spec_container("app", "300m", "256Mi")],
+        );
+        pod.node_name = node.to_string();
+        pod
+    }
+
+    #[test]
+    fn reads_capacity_allocatable_and_labels() {
+        let node = Node {
+            metadata: kube::api::ObjectMeta {
+                name: Some("node-1".to_string()),
+                labels: Some(BTreeMap::from([(
+                    INSTANCE_TYPE_LABEL.to_string(),
+                    "m5.xlarge".to_string(),
+                )])),
+                ..Default::default()
+            },
+            spec: Some(NodeSpec {
+                unschedulable: Some(true),
+                ..Default::default()
+            }),
+            status: Some(NodeStatus {
+                capacity: Some(quantities("4", "16Gi")),
+                allocatable: Some(quantities("3920m", "15Gi")),
+                ..Default::default()
+            }),
+        };
+        let info = node_info("prod", &node);
+        assert_eq!(info.instance_type.as_deref(), Some("m5.xlarge"));
+        assert!(info.unschedulable);
+        assert_eq!(info.capacity_cpu_millicores, Some(4000));
+        assert_eq!(info.allocatable_cpu_millicores, Some(3920));
+        assert_eq!(info.allocatable_memory_bytes, Some(15 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn sums_effective_requests_and_set_limits_of_scheduled_pods() {
+        let mut nodes = [node("node-1", Some(quantities("2", "1Gi")))];
+        let pods = [
+            pod("prod", "node-1"),
+            unlimited_pod("node-1"),
+            // Unscheduled pods and pods on a node of the same name in another cluster do not count.
+            pod("prod", ""),
+            pod("staging", "node-1"),
+        ];
+        add_pods(&mut nodes, &pods, DisplayUnits::default());
+        let node = &nodes[0];
+        assert_eq!(node.pod_count, 2);
+        // The init container's 500m dominates the first pod's effective request.
+        assert_eq!(node.requested_cpu_millicores, 500 + 300);
+        assert_eq!(node.requested_memory_bytes, (64 + 256) * 1024 * 1024);
+        // Like kubectl, limits add up the app containers that set one and skip init containers.
+        assert_eq!(node.limit_cpu_millicores, 200);
+        assert_eq!(node.limit_memory_bytes, 128 * 1024 * 1024);
+        assert_eq!(node.requested_cpu_percent, Some(40.0));
+        assert_eq!(node.limit_memory_percent, Some(12.5));
+        assert_eq!(node.display.requested_cpu, 0.8);
+    }
+
+    #[test]
+    fn percentages_need_allocatable() {
+        let mut nodes = [
+            node("missing", None),
+            node("zero", Some(quantities("0", "0"))),
+        ];
+        let pods = [pod("prod", "missing"), pod("prod", "zero")];
+        add_pods(&mut nodes, &pods, DisplayUnits::default());
+        for node in &nodes {
+            assert_eq!(node.pod_count, 1);
+            assert_eq!(node.requested_cpu_percent, None);
+            assert_eq!(node.requested_memory_percent, None);
+            assert_eq!(node.limit_cpu_percent, None);
+            assert_eq!(node.limit_memory_percent, None);
+        }
+        assert_eq!(percent(50, Some(200)), Some(25.0));
+    }
+}
@@ -0,0 +1,311 @@
+use axum::response::Html;
+use axum::Json;
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Value};
+
+use crate::error::Problem;
+use crate::nodes::{NodesRequestBody, NodesResponse};
+use crate::quotas::QuotasResponse;
+use crate::recommendations::{RecommendationsRequestBody, RecommendationsResponse};
+use crate::snapshots::{SnapshotResponse, SnapshotsResponse};
+use crate::summary::{SummaryRequestBody, SummaryResponse};
+use crate::workloads::WorkloadsResponse;
+use crate::{PodComputeInfoRequestBody, PodComputeInfoResponse};
+
+// Loaded from unpkg and pinned to an exact release, without subresource integrity: the browser trusts whatever unpkg
+// serves under that version, and the page needs access to unpkg.com.
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>kube-workload-compute-details-api</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
+
+// The aggregating endpoints take the pods query, apart from its paging fields.
+const NOT_PAGED: &str =
+    "Covers every matching pod; `page_size` and `cursor` of the pods query are rejected with 400.";
+
+pub async fn get_openapi() -> Json<Value> {
+    Json(spec())
+}
+
+pub async fn get_docs() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
+
+// Schemas are derived from the request and response types themselves, so the document cannot drift from the handlers.
+pub fn spec() -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let pods_request = gen.subschema_for::<PodComputeInfoRequestBody>();
+    let pods_response = gen.subschema_for::<PodComputeInfoResponse>();
+    let summary_request = gen.subschema_for::<SummaryRequestBody>();
+    let summary_response = gen.subschema_for::<SummaryResponse>();
+    let workloads_response = gen.subschema_for::<WorkloadsResponse>();
+    let nodes_request = gen.subschema_for::<NodesRequestBody>();
+    let nodes_response = gen.subschema_for::<NodesResponse>();
+    let quotas_response = gen.subschema_for::<QuotasResponse>();
+    let recommendations_request = gen.subschema_for::<RecommendationsRequestBody>();
+    let recommendations_response = gen.subschema_for::<RecommendationsResponse>();
+    let snapshots_response = gen.subschema_for::<SnapshotsResponse>();
+    let snapshot_response = gen.subschema_for::<SnapshotResponse>();
+    let problem = gen.subschema_for::<Problem>();
+    let schemas = gen.take_definitions();
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": {
+            "/api/compute-info/pods": {
+                "post": {
+                    "summary": "Compute requests and limits of the pods matching the query",
+                    "parameters": [{
+                        "name": "format",
+                        "in": "query",
+                        "required": false,
+                        "description": "Set to `csv` for one row per container; `Accept: text/csv` does the same.",
+                        "schema": { "type": "string", "enum": ["json", "csv"] },
+                    }],
+                    "requestBody": json_body(&pods_request),
+                    "responses": responses(&problem, json!({
+                        "description": "Pods matching the query",
+                        "headers": {
+                            "X-Next-Cursor": {
+                                "description": "CSV only: cursor of the next page.",
+                                "schema": { "type": "string" },
+                            },
+                            "X-Partial-Failures": {
+                                "description": "CSV only: clusters or namespaces that could not be read, as `cluster/namespace=status` separated by commas.",
+                                "schema": { "type": "string" },
+                            },
+                            "X-Warning-Count": {
+                                "description": "CSV only: number of warnings the JSON response would list.",
+                                "schema": { "type": "integer" },
+                            },
+                        },
+                        "content": {
+                            "application/json": { "schema": pods_response },
+                            "text/csv": { "schema": { "type": "string" } },
+                        },
+                    })),
+                },
+            },
+            "/api/compute-info/summary": {
+                "post": {
+                    "summary": "Requested resources summed per group",
+                    "description": NOT_PAGED,
+                    "requestBody": json_body(&summary_request),
+                    "responses": responses(&problem, json!({
+                        "description": "Totals per group",
+                        "content": { "application/json": { "schema": summary_response } },
+                    })),
+                },
+            },
+            "/api/compute-info/workloads": {
+                "post": {
+                    "summary": "Pods aggregated per owning Deployment, StatefulSet, DaemonSet, CronJob or other controller",
+                    "description": NOT_PAGED,
+                    "requestBody": json_body(&pods_request),
+                    "responses": responses(&problem, json!({
+                        "description": "Totals per workload",
+                        "content": { "application/json": { "schema": workloads_response } },
+                    })),
+                },
+            },
+            "/api/compute-info/nodes": {
+                "post": {
+                    "summary": "Node capacity and allocatable next to the requests and limits of the pods scheduled there",
+                    "requestBody": json_body(&nodes_request),
+                    "responses": responses(&problem, json!({
+                        "description": "Allocation per node",
+                        "content": { "application/json": { "schema": nodes_response } },
+                    })),
+                },
+            },
+            "/api/compute-info/quotas": {
+                "post": {
+                    "summary": "ResourceQuotas and LimitRanges per namespace next to the totals of the matching pods",
+                    "description": NOT_PAGED,
+                    "requestBody": json_body(&pods_request),
+                    "responses": responses(&problem, json!({
+                        "description": "Quotas, limit ranges and pod totals per namespace",
+                        "content": { "application/json": { "schema": quotas_response } },
+                    })),
+                },
+            },
+            "/api/compute-info/recommendations": {
+                "post": {
+                    "summary": "Suggested container requests from a percentile of observed usage plus headroom",
+                    "description": NOT_PAGED,
+                    "requestBody": json_body(&recommendations_request),
+                    "responses": responses(&problem, json!({
+                        "description": "Recommendations per workload container",
+                        "content": { "application/json": { "schema": recommendations_response } },
+                    })),
+                },
+            },
+            "/api/compute-info/snapshots": {
+                "get": {
+                    "summary": "Stored snapshots, oldest first",
+                    "responses": responses(&problem, json!({
+                        "description": "Snapshots",
+                        "content": { "application/json": { "schema": snapshots_response } },
+                    })),
+                },
+            },
+            "/api/compute-info/snapshot": {
+                "get": {
+                    "summary": "Pods of the last snapshot taken at or before a time",
+                    "parameters": [
+                        query_parameter("at", "RFC 3339 timestamp; defaults to now"),
+                        query_parameter("cluster", "Only pods of this cluster"),
+                        query_parameter("namespace", "Only pods of this namespace"),
+                        query_parameter("maintainer", "Only pods of this maintainer"),
+                    ],
+                    "responses": responses(&problem, json!({
+                        "description": "The snapshot",
+                        "content": { "application/json": { "schema": snapshot_response } },
+                    })),
+                },
+            },
+            "/api/openapi.json": {
+                "get": {
+                    "summary": "This OpenAPI document",
+                    "responses": {
+                        "200": {
+                            "description": "OpenAPI 3 document",
+                            "content": { "application/json": { "schema": { "type": "object" } } },
+                        },
+                    },
+                },
+            },
+            "/api/docs": {
+                "get": {
+                    "summary": "Swagger UI for this OpenAPI document",
+                    "responses": {
+                        "200": {
+                            "description": "HTML page",
+                            "content": { "text/html": { "schema": { "type": "string" } } },
+                        },
+                    },
+                },
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Prometheus exposition of requested and limit resources per container",
+                    "responses": {
+                        "200": {
+                            "description": "Prometheus text format",
+                            "content": { "text/plain": { "schema": { "type": "string" } } },
+                        },
+                    },
+                },
+            },
+        },
+        "components": { "schemas": schemas },
+    })
+}
+
+fn json_body(schema: &schemars::schema::Schema) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+fn query_parameter(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+// Every query endpoint shares the same problem details responses for invalid input and Kubernetes failures.
+fn responses(problem: &schemars::schema::Schema, ok: Value) -> Value {
+    let mut responses = json!({ "200": ok });
+    for (status, description) in [
+        ("400", "The query is invalid"),
+        (
+            "401",
+            "The service could not authenticate against the cluster",
+        ),
+        (
+            "403",
+            "The service is not allowed to read the requested resources",
+        ),
+        ("404", "A requested resource does not exist"),
+        ("503", "The Kubernetes API is unavailable"),
+        ("504", "The Kubernetes API timed out"),
+    ] {
+        responses[status] = json!({
+            "description": description,
+            "content": { "application/problem+json": { "schema": problem } },
+        });
+    }
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(reference)) => found.push(reference),
+                        _ => refs(value, found),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|value| refs(value, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn documents_every_route() {
+        let spec = spec();
+        let paths = spec["paths"].as_object().unwrap();
+        for (path, _) in crate::routes() {
+            assert!(paths.contains_key(path), "{} is not documented", path);
+        }
+        let routes: Vec<&str> = crate::routes().into_iter().map(|(path, _)| path).collect();
+        for path in paths.keys() {
+            assert!(routes.contains(&path.as_str()), "{} is not routed", path);
+        }
+    }
+
+    #[test]
+    fn every_reference_resolves() {
+        let spec = spec();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+        let mut found = Vec::new();
+        refs(&spec, &mut found);
+        assert!(!found.is_empty());
+        for reference in found {
+            let name = reference
+                .strip_prefix("#/components/schemas/")
+                .unwrap_or_else(|| panic!("{} does not point at a schema", reference));
+            assert!(schemas.contains_key(name), "{} does not resolve", reference);
+        }
+    }
+}
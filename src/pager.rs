@@ -0,0 +1,201 @@
+use futures::{stream, Stream};
+use k8s_openapi::api::core::v1::Pod;
+use kube::api::ListParams;
+use kube::Api;
+
+const LIST_PAGE_SIZE: u32 = 500;
+const MAX_LIST_RESTARTS: u32 = 3;
+
+struct PageCursor {
+    continue_token: Option<String>,
+    restarts: u32,
+    done: bool,
+}
+
+// Lists pods in chunks of LIST_PAGE_SIZE so a huge namespace never has to fit into a single response.
+// When a continue token expires (410 Gone) the listing starts over, so consumers must tolerate pods they have already seen.
+pub fn pod_pages(
+    api: Api<Pod>,
+    list_params: ListParams,
+) -> impl Stream<Item = kube::Result<Vec<Pod>>> {
+    let cursor = PageCursor {
+        continue_token: None,
+        restarts: 0,
+        done: false,
+    };
+    stream::try_unfold(cursor, move |mut cursor| {
+        let api = api.clone();
+        let list_params = list_params.clone();
+        async move {
+            if cursor.done {
+                return Ok(None);
+            }
+            loop {
+                let mut params = list_params.clone().limit(LIST_PAGE_SIZE);
+                if let Some(token) = &cursor.continue_token {
+                    params = params.continue_token(token);
+                }
+                match api.list(&params).await {
+                    Ok(page) => {
+                        cursor.continue_token = page.metadata.continue_.filter(|t| !t.is_empty());
+                        cursor.done = cursor.continue_token.is_none();
+                        return Ok(Some((page.items, cursor)));
+                    }
+                    Err(kube::Error::Api(e))
+                        if e.code == 410
+                            && cursor.continue_token.is_some()
+                            && cursor.restarts < MAX_LIST_RESTARTS =>
+                    {
+                        eprintln!("Continue token expired, restarting pod listing: {}", e);
+                        cursor.continue_token = None;
+                        cursor.restarts += 1;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::tests::{pod_json, pod_list};
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use futures::StreamExt;
+    use serde_json::json;
+
+    fn expired() -> Response {
+        let status = json!({
+            "apiVersion": "v1",
+            "kind": "Status",
+            "metadata": {},
+            "status": "Failure",
+            "message": "The provided continue parameter is too old",
+            "reason": "Expired",
+            "code": 410,
+        });
+        (StatusCode::GONE, Json(status)).into_response()
+    }
+
+    // Serves pods `a` and `b` and then `c` behind the continue token `page-2`, which expires `expiries` times
+    // before it is served. Every request's continue token is recorded.
+    fn expiring_router(
+        expiries: usize,
+        requests: Arc<Mutex<Vec<HashMap<String, String>>>>,
+    ) -> Router {
+        let expiries = Arc::new(Mutex::new(expiries));
+        Router::new().route(
+            "/api/v1/namespaces/shop/pods",
+            get(move |Query(params): Query<HashMap<String, String>>| {
+                requests.lock().unwrap().push(params.clone());
+                let expiries = Arc::clone(&expiries);
+                async move {
+                    match params.get("continue").map(String::as_str) {
+                        None => Json(pod_list(
+                            vec![pod_json("shop", "a"), pod_json("shop", "b")],
+                            "page-2",
+                        ))
+                        .into_response(),
+                        Some("page-2") => {
+                            let mut expiries = expiries.lock().unwrap();
+                            if *expiries > 0 {
+                                *expiries -= 1;
+                                return expired();
+                            }
+                            Json(pod_list(vec![pod_json("shop", "c")], "")).into_response()
+                        }
+                        Some(_) => StatusCode::BAD_REQUEST.into_response(),
+                    }
+                }
+            }),
+        )
+    }
+
+    fn names(page: &[Pod]) -> Vec<&str> {
+        page.iter()
+            .map(|pod| pod.metadata.name.as_deref().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lists_in_chunks_following_continue_tokens() {
+        let requests = Arc::default();
+        let client = crate::tests::fake_apiserver(expiring_router(0, Arc::clone(&requests))).await;
+        let pages: Vec<Vec<Pod>> =
+            pod_pages(Api::namespaced(client, "shop"), ListParams::default())
+                .map(Result::unwrap)
+                .collect()
+                .await;
+        let pages: Vec<Vec<&str>> = pages.iter().map(|page| names(page)).collect();
+        assert_eq!(pages, [vec!["a", "b"], vec!["c"]]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|params| params.get("limit").map(String::as_str) == Some("500")));
+    }
+
+    #[tokio::test]
+    async fn restarts_the_listing_when_the_continue_token_expires() {
+        let requests = Arc::default();
+        let client = crate::tests::fake_apiserver(expiring_router(1, Arc::clone(&requests))).await;
+        let pages: Vec<Vec<Pod>> =
+            pod_pages(Api::namespaced(client, "shop"), ListParams::default())
+                .map(Result::unwrap)
+                .collect()
+                .await;
+        let pages: Vec<Vec<&str>> = pages.iter().map(|page| names(page)).collect();
+        // The first page comes again after the restart.
+        assert_eq!(pages, [vec!["a", "b"], vec!["a", "b"], vec!["c"]]);
+        let tokens: Vec<Option<String>> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|params| params.get("continue").cloned())
+            .collect();
+        let page_2 = Some("page-2".to_string());
+        assert_eq!(tokens, [None, page_2.clone(), None, page_2]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_repeated_expiries() {
+        let client =
+            crate::tests::fake_apiserver(expiring_router(usize::MAX, Arc::default())).await;
+        let pages: Vec<kube::Result<Vec<Pod>>> =
+            pod_pages(Api::namespaced(client, "shop"), ListParams::default())
+                .collect()
+                .await;
+        assert_eq!(pages.len(), 1 + MAX_LIST_RESTARTS as usize + 1);
+        match pages.last().unwrap() {
+            Err(kube::Error::Api(e)) => assert_eq!(e.code, 410),
+            other => panic!("expected an expired continue token, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_restarted_listing_yields_each_pod_once() {
+        let client = crate::tests::fake_apiserver(expiring_router(1, Arc::default())).await;
+        let cluster = crate::cluster::Cluster {
+            name: "default".to_string(),
+            client,
+            pod_cache: None,
+        };
+        let query = crate::PodQuery {
+            namespaces: vec!["shop".to_string()],
+            all_namespaces: false,
+            ..crate::tests::pod_query()
+        };
+        let pods_info = crate::list_pods_info(&cluster, &query).await.unwrap();
+        let names: Vec<&str> = pods_info.pods.iter().map(|pod| pod.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(pods_info.errors.is_empty());
+    }
+}
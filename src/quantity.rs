@@ -0,0 +1,247 @@
+use anyhow::{anyhow, bail};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const KIB: f64 = 1024.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuUnit {
+    #[default]
+    Cores,
+    Millicores,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize, JsonSchema)]
+pub enum MemoryUnit {
+    #[serde(rename = "bytes")]
+    Bytes,
+    KiB,
+    #[default]
+    MiB,
+    GiB,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, JsonSchema)]
+pub struct DisplayUnits {
+    pub cpu: CpuUnit,
+    pub memory: MemoryUnit,
+}
+
+impl CpuUnit {
+    pub fn convert_millicores(self, millicores: u64) -> f64 {
+        match self {
+            CpuUnit::Cores => millicores as f64 / 1000.0,
+            CpuUnit::Millicores => millicores as f64,
+        }
+    }
+}
+
+impl MemoryUnit {
+    pub fn convert_bytes(self, bytes: u64) -> f64 {
+        match self {
+            MemoryUnit::Bytes => bytes as f64,
+            MemoryUnit::KiB => bytes as f64 / KIB,
+            MemoryUnit::MiB => bytes as f64 / (KIB * KIB),
+            MemoryUnit::GiB => bytes as f64 / (KIB * KIB * KIB),
+        }
+    }
+}
+
+// A quantity is mantissa * 10^exp10 * 2^exp2; keeping it in integers avoids float drift on large byte counts.
+#[derive(Debug, PartialEq)]
+struct ParsedQuantity {
+    mantissa: u128,
+    exp10: i32,
+    exp2: u32,
+}
+
+/// CPU in millicores, rounded up the same way the scheduler rounds fractional millicores.
+pub fn cpu_millicores(quantity: &Quantity) -> anyhow::Result<u64> {
+    parse(&quantity.0)?.scaled_ceil(3)
+}
+
+/// Memory in bytes, rounded up to a whole byte.
+pub fn memory_bytes(quantity: &Quantity) -> anyhow::Result<u64> {
+    parse(&quantity.0)?.scaled_ceil(0)
+}
+
+impl ParsedQuantity {
+    fn scaled_ceil(&self, extra_exp10: i32) -> anyhow::Result<u64> {
+        let overflow = || anyhow!("quantity is too large");
+        let mut value = self
+            .mantissa
+            .checked_mul(1u128.checked_shl(self.exp2).ok_or_else(overflow)?)
+            .ok_or_else(overflow)?;
+        let exp10 = self.exp10.checked_add(extra_exp10).ok_or_else(overflow)?;
+        if exp10 >= 0 {
+            let factor = 10u128.checked_pow(exp10 as u32).ok_or_else(overflow)?;
+            value = value.checked_mul(factor).ok_or_else(overflow)?;
+        } else {
+            value = match 10u128.checked_pow(exp10.unsigned_abs()) {
+                Some(divisor) => value.div_ceil(divisor),
+                None => u128::from(value > 0),
+            };
+        }
+        u64::try_from(value).map_err(|_| overflow())
+    }
+}
+
+fn parse(s: &str) -> anyhow::Result<ParsedQuantity> {
+    let s = s.trim();
+    let unsigned = match s.strip_prefix('-') {
+        Some(_) => bail!("negative quantity {:?} is not a valid resource amount", s),
+        None => s.strip_prefix('+').unwrap_or(s),
+    };
+    let number_end = unsigned
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(unsigned.len());
+    let (number, suffix) = unsigned.split_at(number_end);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        bail!("quantity {:?} has no numeric part", s);
+    }
+    let digits = format!("{}{}", whole, fraction);
+    let mantissa: u128 = digits
+        .parse()
+        .map_err(|_| anyhow!("quantity {:?} has too many digits", s))?;
+    let (suffix_exp10, exp2) = parse_suffix(suffix)
+        .ok_or_else(|| anyhow!("quantity {:?} has an unknown suffix {:?}", s, suffix))?;
+    let exp10 = i32::try_from(fraction.len())
+        .ok()
+        .and_then(|fraction_digits| suffix_exp10.checked_sub(fraction_digits))
+        .ok_or_else(|| anyhow!("quantity {:?} has an exponent out of range", s))?;
+    Ok(ParsedQuantity {
+        mantissa,
+        exp10,
+        exp2,
+    })
+}
+
+fn parse_suffix(suffix: &str) -> Option<(i32, u32)> {
+    let scale = match suffix {
+        "" => (0, 0),
+        "n" => (-9, 0),
+        "u" => (-6, 0),
+        "m" => (-3, 0),
+        "k" => (3, 0),
+        "M" => (6, 0),
+        "G" => (9, 0),
+        "T" => (12, 0),
+        "P" => (15, 0),
+        "E" => (18, 0),
+        "Ki" => (0, 10),
+        "Mi" => (0, 20),
+        "Gi" => (0, 30),
+        "Ti" => (0, 40),
+        "Pi" => (0, 50),
+        "Ei" => (0, 60),
+        _ => {
+            let exponent = suffix
+                .strip_prefix('e')
+                .or_else(|| suffix.strip_prefix('E'))?;
+            let unsigned = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if unsigned.is_empty() || !unsigned.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            (exponent.parse().ok()?, 0)
+        }
+    };
+    Some(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantity(s: &str) -> Quantity {
+        Quantity(s.to_string())
+    }
+
+    #[test]
+    fn parses_accepted_quantities() {
+        let cases: &[(&str, u64, u64)] = &[
+            // (quantity, millicores, bytes)
+            ("250m", 250, 1),
+            ("1", 1000, 1),
+            ("+1", 1000, 1),
+            ("0.1", 100, 1),
+            ("1.5", 1500, 2),
+            ("100n", 1, 1),
+            ("1500u", 2, 1),
+            ("2k", 2_000_000, 2_000),
+            ("1Ki", 1_024_000, 1_024),
+            ("1Gi", 1_073_741_824_000, 1_073_741_824),
+            ("1.5Gi", 1_610_612_736_000, 1_610_612_736),
+            ("512Mi", 536_870_912_000, 536_870_912),
+            ("1e3", 1_000_000, 1_000),
+            ("1E3", 1_000_000, 1_000),
+            ("5e-3", 5, 1),
+            ("1M", 1_000_000_000, 1_000_000),
+            (" 2 ", 2000, 2),
+        ];
+        for (s, millicores, bytes) in cases {
+            assert_eq!(
+                cpu_millicores(&quantity(s)).unwrap(),
+                *millicores,
+                "{:?}",
+                s
+            );
+            assert_eq!(memory_bytes(&quantity(s)).unwrap(), *bytes, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn distinguishes_exa_from_exponents() {
+        assert_eq!(
+            memory_bytes(&quantity("1E")).unwrap(),
+            1_000_000_000_000_000_000
+        );
+        assert_eq!(memory_bytes(&quantity("1e3")).unwrap(), 1_000);
+        assert_eq!(memory_bytes(&quantity("1Ei")).unwrap(), 1 << 60);
+    }
+
+    #[test]
+    fn rejects_invalid_quantities() {
+        for s in [
+            "-1", "-250m", ".", "", "1x", "1e", "1e+", "m", "1.2.3", "1 Gi", "1mi",
+        ] {
+            assert!(memory_bytes(&quantity(s)).is_err(), "{:?} was accepted", s);
+            assert!(
+                cpu_millicores(&quantity(s)).is_err(),
+                "{:?} was accepted",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_quantities_that_overflow() {
+        for s in [
+            "20E",
+            "16Ei",
+            "1e40",
+            "99999999999999999999999999999999999999999",
+        ] {
+            assert!(memory_bytes(&quantity(s)).is_err(), "{:?} was accepted", s);
+        }
+        // Fits in bytes but not once scaled to millicores.
+        assert!(memory_bytes(&quantity("1E")).is_ok());
+        assert!(cpu_millicores(&quantity("1E")).is_err());
+        // The largest exponent only overflows once millicores add three more.
+        assert!(cpu_millicores(&quantity("1e2147483647")).is_err());
+        assert!(memory_bytes(&quantity("0.5e-2147483648")).is_err());
+    }
+
+    #[test]
+    fn cpu_rounds_up_to_whole_millicores() {
+        assert_eq!(cpu_millicores(&quantity("1n")).unwrap(), 1);
+        assert_eq!(cpu_millicores(&quantity("1000001n")).unwrap(), 2);
+        assert_eq!(cpu_millicores(&quantity("0.0001")).unwrap(), 1);
+        assert_eq!(cpu_millicores(&quantity("0.0015")).unwrap(), 2);
+        assert_eq!(cpu_millicores(&quantity("1e-400")).unwrap(), 1);
+        assert_eq!(cpu_millicores(&quantity("0")).unwrap(), 0);
+        assert_eq!(cpu_millicores(&quantity("0.0000")).unwrap(), 0);
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{Json, State};
+use futures::{stream, StreamExt};
+use k8s_openapi::api::core::v1::{LimitRange, LimitRangeItem, ResourceQuota};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::Api;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::cluster::Cluster;
+use crate::error::{self, ApiError, QueryError};
+use crate::namespaces::{self, NamespaceScope};
+use crate::quantity::{self, DisplayUnits};
+use crate::{
+    build_pod_query, cache_age_seconds, get_pods_info, query_clusters, reject_paging,
+    watch_healthy, AppState, AppliedFilter, ContainerKind, PodComputeInfoRequestBody, PodQuery,
+    PodsInfo,
+};
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct QuotasResponse {
+    filter: AppliedFilter,
+    cache_age_seconds: Option<f64>,
+    watch_healthy: Option<bool>,
+    units: DisplayUnits,
+    warnings: Vec<String>,
+    errors: Vec<QueryError>,
+    namespaces: Vec<NamespaceQuotas>,
+}
+
+// Pod totals cover the pods matching the query, next to what the namespace's quotas count.
+#[derive(Debug, Serialize, Clone, Default, JsonSchema)]
+pub struct NamespaceQuotas {
+    cluster: String,
+    namespace: String,
+    pod_count: usize,
+    requested_cpu_millicores: u64,
+    requested_memory_bytes: u64,
+    limit_cpu_millicores: u64,
+    limit_memory_bytes: u64,
+    quotas: Vec<QuotaInfo>,
+    limit_ranges: Vec<LimitRangeInfo>,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct QuotaInfo {
+    name: String,
+    resources: Vec<QuotaResource>,
+}
+
+// `hard_value`, `used_value` and `headroom` are in millicores for CPU resources, bytes for memory and storage, and
+// plain counts otherwise. Headroom is negative when a quota is exceeded.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct QuotaResource {
+    resource: String,
+    hard: Quantity,
+    used: Option<Quantity>,
+    hard_value: Option<u64>,
+    used_value: Option<u64>,
+    headroom: Option<i64>,
+    used_percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct LimitRangeInfo {
+    name: String,
+    limits: Vec<LimitRangeItem>,
+}
+
+struct Policies {
+    namespaces: Vec<String>,
+    quotas: Vec<ResourceQuota>,
+    limit_ranges: Vec<LimitRange>,
+    errors: Vec<QueryError>,
+}
+
+pub async fn get_quotas(
+    State(state): State<AppState>,
+    Json(request_body): Json<PodComputeInfoRequestBody>,
+) -> Result<Json<QuotasResponse>, ApiError> {
+    reject_paging(&request_body).map_err(ApiError::bad_request)?;
+    let mut query = build_pod_query(&state, &request_body).map_err(ApiError::bad_request)?;
+    query.usage_metrics = false;
+    query.pricing = None;
+    let clusters: Vec<&Cluster> = query_clusters(&state, &query).collect();
+    let (pods_info, policies) = futures::future::join(
+        get_pods_info(&state, &query),
+        futures::future::join_all(
+            clusters
+                .iter()
+                .map(|cluster| namespace_policies(cluster, &query)),
+        ),
+    )
+    .await;
+    let PodsInfo {
+        pods,
+        mut errors,
+        warnings,
+    } = pods_info?;
+
+    let mut namespaces: BTreeMap<(String, String), NamespaceQuotas> = BTreeMap::new();
+    for (cluster, result) in clusters.iter().zip(policies) {
+        let policies = match result {
+            Ok(policies) => policies,
+            Err(e) => {
+                eprintln!("Error reading quotas of cluster {}: {}", cluster.name, e);
+                errors.push(QueryError {
+                    cluster: cluster.name.clone(),
+                    namespace: None,
+                    status: e.status().as_u16(),
+                    detail: e.to_string(),
+                });
+                continue;
+            }
+        };
+        errors.extend(policies.errors);
+        for namespace in policies.namespaces {
+            namespace_entry(&mut namespaces, &cluster.name, &namespace);
+        }
+        for quota in policies.quotas {
+            let namespace = quota.metadata.namespace.clone().unwrap_or_default();
+            namespace_entry(&mut namespaces, &cluster.name, &namespace)
+                .quotas
+                .push(quota_info(&quota));
+        }
+        for limit_range in policies.limit_ranges {
+            let namespace = limit_range.metadata.namespace.clone().unwrap_or_default();
+            namespace_entry(&mut namespaces, &cluster.name, &namespace)
+                .limit_ranges
+                .push(LimitRangeInfo {
+                    name: limit_range.metadata.name.clone().unwrap_or_default(),
+                    limits: limit_range.spec.map(|spec| spec.limits).unwrap_or_default(),
+                });
+        }
+    }
+    for pod in &pods {
+        let totals = namespace_entry(&mut namespaces, &pod.cluster, &pod.namespace);
+        totals.pod_count += 1;
+        totals.requested_cpu_millicores += pod.totals.requested_cpu_millicores;
+        totals.requested_memory_bytes += pod.totals.requested_memory_bytes;
+        for container in pod
+            .containers
+            .iter()
+            .filter(|container| container.kind != ContainerKind::Init)
+        {
+            let resources = &container.compute_resources;
+            totals.limit_cpu_millicores += resources.limit_cpu_millicores.unwrap_or(0);
+            totals.limit_memory_bytes += resources.limit_memory_bytes.unwrap_or(0);
+        }
+    }
+
+    Ok(Json(QuotasResponse {
+        filter: query.applied_filter(request_body.maintainers.unwrap_or_default()),
+        cache_age_seconds: cache_age_seconds(&state, &query),
+        watch_healthy: watch_healthy(&state, &query),
+        units: query.units,
+        warnings,
+        errors,
+        namespaces: namespaces.into_values().collect(),
+    }))
+}
+
+fn namespace_entry<'a>(
+    namespaces: &'a mut BTreeMap<(String, String), NamespaceQuotas>,
+    cluster: &str,
+    namespace: &str,
+) -> &'a mut NamespaceQuotas {
+    namespaces
+        .entry((cluster.to_string(), namespace.to_string()))
+        .or_insert_with(|| NamespaceQuotas {
+            cluster: cluster.to_string(),
+            namespace: namespace.to_string(),
+            ..Default::default()
+        })
+}
+
+// Lists quotas and limit ranges cluster-wide or per namespace, like pods; a failing namespace becomes an error entry.
+async fn namespace_policies(cluster: &Cluster, query: &PodQuery) -> Result<Policies, ApiError> {
+    let client = &cluster.client;
+    let scope = namespaces::resolve_scope(
+        client,
+        &query.namespaces,
+        query.all_namespaces,
+        &query.exclude_namespaces,
+    )
+    .await?;
+    let (namespaces, targets) = match scope {
+        NamespaceScope::All => (Vec::new(), vec![None]),
+        NamespaceScope::Namespaces(namespaces) => {
+            let targets = namespaces.iter().cloned().map(Some).collect();
+            (namespaces, targets)
+        }
+    };
+    let listings: Vec<_> = stream::iter(targets)
+        .map(|namespace| async move {
+            let listing = list_policies(client, namespace.as_deref()).await;
+            (namespace, listing)
+        })
+        .buffer_unordered(query.list_concurrency)
+        .collect()
+        .await;
+
+    let mut policies = Policies {
+        namespaces,
+        quotas: Vec::new(),
+        limit_ranges: Vec::new(),
+        errors: Vec::new(),
+    };
+    let in_scope = |namespace: &Option<String>| {
+        !namespaces::is_excluded(
+            namespace.as_deref().unwrap_or_default(),
+            &query.exclude_namespaces,
+        )
+    };
+    for (namespace, listing) in listings {
+        match listing {
+            Ok((quotas, limit_ranges)) => {
+                policies.quotas.extend(
+                    quotas
+                        .into_iter()
+                        .filter(|quota| in_scope(&quota.metadata.namespace)),
+                );
+                policies.limit_ranges.extend(
+                    limit_ranges
+                        .into_iter()
+                        .filter(|limit_range| in_scope(&limit_range.metadata.namespace)),
+                );
+            }
+            Err(e) => {
+                eprintln!(
+                    "Error listing quotas of cluster {} in {:?}: {}",
+                    cluster.name, namespace, e
+                );
+                policies.errors.push(QueryError {
+                    cluster: cluster.name.clone(),
+                    namespace,
+                    status: error::kube_status(&e).as_u16(),
+                    detail: e.to_string(),
+                });
+            }
+        }
+    }
+    Ok(policies)
+}
+
+async fn list_policies(
+    client: &kube::Client,
+    namespace: Option<&str>,
+) -> kube::Result<(Vec<ResourceQuota>, Vec<LimitRange>)> {
+    let (quotas, limit_ranges): (Api<ResourceQuota>, Api<LimitRange>) = match namespace {
+        Some(namespace) => (
+            Api::namespaced(client.clone(), namespace),
+            Api::namespaced(client.clone(), namespace),
+        ),
+        None => (Api::all(client.clone()), Api::all(client.clone())),
+    };
+    let (quotas, limit_ranges) = futures::future::try_join(
+        quotas.list(&Default::default()),
+        limit_ranges.list(&Default::default()),
+    )
+    .await?;
+    Ok((quotas.items, limit_ranges.items))
+}
+
+fn quota_info(quota: &ResourceQuota) -> QuotaInfo {
+    let status = quota.status.clone().unwrap_or_default();
+    let used = status.used.unwrap_or_default();
+    let hard = status
+        .hard
+        .or_else(|| quota.spec.as_ref().and_then(|spec| spec.hard.clone()))
+        .unwrap_or_default();
+    QuotaInfo {
+        name: quota.metadata.name.clone().unwrap_or_default(),
+        resources: hard
+            .into_iter()
+            .map(|(resource, hard)| {
+                let used = used.get(&resource).cloned();
+                let hard_value = value(&resource, &hard);
+                let used_value = used.as_ref().and_then(|used| value(&resource, used));
+                QuotaResource {
+                    headroom: hard_value
+                        .zip(used_value)
+                        .map(|(hard, used)| hard as i64 - used as i64),
+                    used_percent: hard_value
+                        .zip(used_value)
+                        .filter(|(hard, _)| *hard > 0)
+                        .map(|(hard, used)| used as f64 * 100.0 / hard as f64),
+                    resource,
+                    hard,
+                    used,
+                    hard_value,
+                    used_value,
+                }
+            })
+            .collect(),
+    }
+}
+
+fn value(resource: &str, quantity: &Quantity) -> Option<u64> {
+    if resource == "cpu" || resource.ends_with(".cpu") {
+        quantity::cpu_millicores(quantity).ok()
+    } else {
+        quantity::memory_bytes(quantity).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{ResourceQuotaSpec, ResourceQuotaStatus};
+
+    type Values<'a> = &'a [(&'a str, &'a str)];
+
+    fn quantities(values: Values) -> BTreeMap<String, Quantity> {
+        values
+            .iter()
+            .map(|(resource, value)| (resource.to_string(), Quantity(value.to_string())))
+            .collect()
+    }
+
+    fn quota(spec_hard: Values, status: Option<(Values, Values)>) -> ResourceQuota {
+        ResourceQuota {
+            metadata: kube::api::ObjectMeta {
+                name: Some("compute".to_string()),
+                ..Default::default()
+            },
+            spec: Some(ResourceQuotaSpec {
+                hard: Some(quantities(spec_hard)),
+                ..Default::default()
+            }),
+            status: status.map(|(hard, used)| ResourceQuotaStatus {
+                hard: Some(quantities(hard)),
+                used: Some(quantities(used)),
+            }),
+        }
+    }
+
+    fn resource<'a>(info: &'a QuotaInfo, name: &str) -> &'a QuotaResource {
+        info.resources
+            .iter()
+            .find(|resource| resource.resource == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn normalizes_cpu_memory_and_count_resources() {
+        let hard = [
+            ("cpu", "4"),
+            ("requests.cpu", "2"),
+            ("limits.cpu", "8"),
+            ("requests.memory", "8Gi"),
+            ("pods", "10"),
+            ("count/deployments.apps", "5"),
+        ];
+        let used = [
+            ("cpu", "4500m"),
+            ("requests.cpu", "1500m"),
+            ("requests.memory", "2Gi"),
+            ("pods", "3"),
+            ("count/deployments.apps", "5"),
+        ];
+        let info = quota_info(&quota(&[], Some((&hard, &used))));
+        assert_eq!(info.name, "compute");
+        assert_eq!(info.resources.len(), 6);
+
+        let requests_cpu = resource(&info, "requests.cpu");
+        assert_eq!(requests_cpu.hard_value, Some(2000));
+        assert_eq!(requests_cpu.used_value, Some(1500));
+        assert_eq!(requests_cpu.headroom, Some(500));
+        assert_eq!(requests_cpu.used_percent, Some(75.0));
+
+        let memory = resource(&info, "requests.memory");
+        assert_eq!(memory.hard_value, Some(8 * 1024 * 1024 * 1024));
+        assert_eq!(memory.used_percent, Some(25.0));
+
+        let pods = resource(&info, "pods");
+        assert_eq!((pods.hard_value, pods.used_value), (Some(10), Some(3)));
+        assert_eq!(pods.headroom, Some(7));
+        assert_eq!(resource(&info, "count/deployments.apps").headroom, Some(0));
+
+        // Without a usage figure there is nothing to compare against.
+        let limits_cpu = resource(&info, "limits.cpu");
+        assert_eq!(limits_cpu.hard_value, Some(8000));
+        assert_eq!(limits_cpu.used, None);
+        assert_eq!(limits_cpu.headroom, None);
+        assert_eq!(limits_cpu.used_percent, None);
+    }
+
+    #[test]
+    fn exceeded_quotas_have_negative_headroom() {
+        let info = quota_info(&quota(&[], Some((&[("cpu", "4")], &[("cpu", "4500m")]))));
+        let cpu = resource(&info, "cpu");
+        assert_eq!(cpu.headroom, Some(-500));
+        assert_eq!(cpu.used_percent, Some(112.5));
+    }
+
+    #[test]
+    fn zero_hard_limits_have_no_used_percent() {
+        let info = quota_info(&quota(
+            &[],
+            Some((&[("services", "0")], &[("services", "0")])),
+        ));
+        let services = resource(&info, "services");
+        assert_eq!(services.headroom, Some(0));
+        assert_eq!(services.used_percent, None);
+    }
+
+    #[test]
+    fn falls_back_to_the_spec_before_the_quota_controller_reports() {
+        let info = quota_info(&quota(&[("requests.memory", "1Gi")], None));
+        let memory = resource(&info, "requests.memory");
+        assert_eq!(memory.hard_value, Some(1024 * 1024 * 1024));
+        assert_eq!(memory.used, None);
+        assert_eq!(memory.headroom, None);
+    }
+}
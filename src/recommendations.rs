@@ -0,0 +1,431 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use axum::extract::{Json, State};
+use chrono::{SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ApiError, QueryError};
+use crate::quantity::DisplayUnits;
+use crate::snapshots::UsageHistory;
+use crate::workloads::Workload;
+use crate::{
+    build_pod_query, cache_age_seconds, get_pods_info, reject_paging, watch_healthy, AppState,
+    AppliedFilter, ContainerKind, DisplayResources, PodComputeInfo, PodComputeInfoRequestBody,
+    PodsInfo,
+};
+
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RecommendationsRequestBody {
+    #[serde(flatten)]
+    pods: PodComputeInfoRequestBody,
+    percentile: Option<f64>,
+    headroom: Option<f64>,
+    window_days: Option<u64>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RecommendationsResponse {
+    filter: AppliedFilter,
+    cache_age_seconds: Option<f64>,
+    watch_healthy: Option<bool>,
+    units: DisplayUnits,
+    percentile: f64,
+    headroom: f64,
+    window: SampleWindow,
+    warnings: Vec<String>,
+    errors: Vec<QueryError>,
+    cpu_savings_millicores: i64,
+    memory_savings_bytes: i64,
+    recommendations: Vec<Recommendation>,
+    withheld: Vec<WithheldRecommendation>,
+}
+
+// Usage samples come from the snapshots taken between `start` and `end` plus the current usage; without snapshots
+// only the current usage counts and `start` equals `end`.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SampleWindow {
+    start: String,
+    end: String,
+    snapshots: bool,
+    min_samples: usize,
+}
+
+// Savings compare the current and recommended requests over all replicas; they are negative when a container is
+// under-requested.
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct Recommendation {
+    cluster: String,
+    namespace: String,
+    workload: Workload,
+    container: String,
+    replicas: usize,
+    samples: usize,
+    requested_cpu_millicores: Option<u64>,
+    requested_memory_bytes: Option<u64>,
+    recommended_cpu: String,
+    recommended_memory: String,
+    recommended_cpu_millicores: u64,
+    recommended_memory_bytes: u64,
+    cpu_savings_millicores: i64,
+    memory_savings_bytes: i64,
+    display: DisplayResources,
+}
+
+// A container with fewer usage samples than `min_samples`, too few for a percentile to mean anything.
+#[derive(Debug, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct WithheldRecommendation {
+    cluster: String,
+    namespace: String,
+    workload: Workload,
+    container: String,
+    samples: usize,
+}
+
+#[derive(Default)]
+struct Samples {
+    replicas: usize,
+    requested_cpu_millicores: Option<u64>,
+    requested_memory_bytes: Option<u64>,
+    cpu_millicores: Vec<u64>,
+    memory_bytes: Vec<u64>,
+}
+
+type SampleKey = (String, String, Workload, String);
+
+pub async fn get_recommendations(
+    State(state): State<AppState>,
+    Json(request_body): Json<RecommendationsRequestBody>,
+) -> Result<Json<RecommendationsResponse>, ApiError> {
+    if !state.config.usage_metrics {
+        return Err(ApiError::bad_request(
+            "recommendations need usage metrics, which are disabled by usage_metrics",
+        ));
+    }
+    let percentile = request_body
+        .percentile
+        .unwrap_or(state.config.recommendation_percentile);
+    let headroom = request_body
+        .headroom
+        .unwrap_or(state.config.recommendation_headroom);
+    validate(percentile, headroom).map_err(ApiError::bad_request)?;
+    let window_days = request_body
+        .window_days
+        .unwrap_or(state.config.recommendation_window_days);
+    if window_days == 0 {
+        return Err(ApiError::bad_request(
+            "window_days must be greater than zero",
+        ));
+    }
+    let min_samples = state.config.recommendation_min_samples;
+    reject_paging(&request_body.pods).map_err(ApiError::bad_request)?;
+    let mut query = build_pod_query(&state, &request_body.pods).map_err(ApiError::bad_request)?;
+    query.pricing = None;
+    let PodsInfo {
+        pods,
+        errors,
+        warnings,
+    } = get_pods_info(&state, &query).await?;
+
+    let end = Utc::now();
+    let mut start = end;
+    let mut history = BTreeMap::new();
+    if let Some(store) = &state.snapshots {
+        start = end - chrono::Duration::days(window_days as i64);
+        // Only the history of the queried workloads is read.
+        let workloads: BTreeSet<(&str, &str, &Workload)> = pods
+            .iter()
+            .map(|pod| (pod.cluster.as_str(), pod.namespace.as_str(), &pod.workload))
+            .collect();
+        for (cluster, namespace, workload) in workloads {
+            let usage = store
+                .usage_history(cluster, namespace, workload, start)
+                .await
+                .map_err(ApiError::Internal)?;
+            for (container, usage) in usage {
+                let key = (
+                    cluster.to_string(),
+                    namespace.to_string(),
+                    workload.clone(),
+                    container,
+                );
+                history.insert(key, usage);
+            }
+        }
+    }
+    let (recommendations, withheld) = recommend(
+        &pods,
+        &history,
+        percentile,
+        headroom,
+        min_samples,
+        query.units,
+    );
+    Ok(Json(RecommendationsResponse {
+        filter: query.applied_filter(request_body.pods.maintainers.unwrap_or_default()),
+        cache_age_seconds: cache_age_seconds(&state, &query),
+        watch_healthy: watch_healthy(&state, &query),
+        units: query.units,
+        percentile,
+        headroom,
+        window: SampleWindow {
+            start: start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            end: end.to_rfc3339_opts(SecondsFormat::Secs, true),
+            snapshots: state.snapshots.is_some(),
+            min_samples,
+        },
+        warnings,
+        errors,
+        cpu_savings_millicores: recommendations
+            .iter()
+            .map(|r| r.cpu_savings_millicores)
+            .sum(),
+        memory_savings_bytes: recommendations.iter().map(|r| r.memory_savings_bytes).sum(),
+        recommendations,
+        withheld,
+    }))
+}
+
+pub fn validate(percentile: f64, headroom: f64) -> anyhow::Result<()> {
+    if !(1.0..=100.0).contains(&percentile) {
+        anyhow::bail!("percentile must be between 1 and 100");
+    }
+    if !(headroom >= 0.0 && headroom.is_finite()) {
+        anyhow::bail!("headroom must be a non-negative number");
+    }
+    Ok(())
+}
+
+// Replicas of a workload share their containers' specs, so their usage forms one sample set per container: the
+// current usage of every replica plus the snapshot history of the same container. Only containers of the queried pods
+// are recommended for, and only once they have `min_samples` samples.
+pub fn recommend(
+    pods: &[PodComputeInfo],
+    history: &BTreeMap<SampleKey, UsageHistory>,
+    percentile: f64,
+    headroom: f64,
+    min_samples: usize,
+    units: DisplayUnits,
+) -> (Vec<Recommendation>, Vec<WithheldRecommendation>) {
+    let mut samples: BTreeMap<SampleKey, Samples> = BTreeMap::new();
+    for pod in pods {
+        for container in pod
+            .containers
+            .iter()
+            .filter(|container| container.kind != ContainerKind::Init)
+        {
+            let key = (
+                pod.cluster.clone(),
+                pod.namespace.clone(),
+                pod.workload.clone(),
+                container.name.clone(),
+            );
+            let entry = samples.entry(key).or_default();
+            entry.replicas += 1;
+            entry.requested_cpu_millicores = container.compute_resources.requested_cpu_millicores;
+            entry.requested_memory_bytes = container.compute_resources.requested_memory_bytes;
+            entry.cpu_millicores.extend(container.used_cpu_millicores);
+            entry.memory_bytes.extend(container.used_memory_bytes);
+        }
+    }
+    for (key, usage) in history {
+        if let Some(entry) = samples.get_mut(key) {
+            entry.cpu_millicores.extend(&usage.cpu_millicores);
+            entry.memory_bytes.extend(&usage.memory_bytes);
+        }
+    }
+
+    let mut recommendations = Vec::new();
+    let mut withheld = Vec::new();
+    for ((cluster, namespace, workload, container), samples) in samples {
+        let sample_count = samples.cpu_millicores.len().min(samples.memory_bytes.len());
+        if sample_count == 0 {
+            continue;
+        }
+        if sample_count < min_samples {
+            withheld.push(WithheldRecommendation {
+                cluster,
+                namespace,
+                workload,
+                container,
+                samples: sample_count,
+            });
+            continue;
+        }
+        let (Some(cpu), Some(memory)) = (
+            percentile_of(samples.cpu_millicores, percentile),
+            percentile_of(samples.memory_bytes, percentile),
+        ) else {
+            continue;
+        };
+        let recommended_cpu_millicores = with_headroom(cpu, headroom).max(1);
+        let recommended_memory_bytes = round_up_to_mib(with_headroom(memory, headroom));
+        let savings = |requested: Option<u64>, recommended: u64| {
+            (requested.unwrap_or(0) as i64 - recommended as i64) * samples.replicas as i64
+        };
+        recommendations.push(Recommendation {
+            cluster,
+            namespace,
+            workload,
+            container,
+            replicas: samples.replicas,
+            samples: sample_count,
+            requested_cpu_millicores: samples.requested_cpu_millicores,
+            requested_memory_bytes: samples.requested_memory_bytes,
+            recommended_cpu: format!("{}m", recommended_cpu_millicores),
+            recommended_memory: format!("{}Mi", recommended_memory_bytes / MIB),
+            recommended_cpu_millicores,
+            recommended_memory_bytes,
+            cpu_savings_millicores: savings(
+                samples.requested_cpu_millicores,
+                recommended_cpu_millicores,
+            ),
+            memory_savings_bytes: savings(samples.requested_memory_bytes, recommended_memory_bytes),
+            display: DisplayResources::new(
+                units,
+                Some(recommended_cpu_millicores),
+                Some(recommended_memory_bytes),
+                None,
+                None,
+            ),
+        });
+    }
+    (recommendations, withheld)
+}
+
+// Nearest-rank percentile.
+fn percentile_of(mut values: Vec<u64>, percentile: f64) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let rank = (percentile / 100.0 * values.len() as f64).ceil() as usize;
+    Some(values[rank.clamp(1, values.len()) - 1])
+}
+
+fn with_headroom(value: u64, headroom: f64) -> u64 {
+    (value as f64 * (1.0 + headroom)).ceil() as u64
+}
+
+fn round_up_to_mib(bytes: u64) -> u64 {
+    bytes.div_ceil(MIB).max(1) * MIB
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod(name: &str, cpu_millicores: u64, memory_mib: u64) -> PodComputeInfo {
+        let mut info = crate::tests::pod_info(
+            "prod",
+            "shop",
+            name,
+            vec![crate::tests::spec_container("app", "500m", "512Mi")],
+        );
+        info.workload = workload();
+        info.containers[0].used_cpu_millicores = Some(cpu_millicores);
+        info.containers[0].used_memory_bytes = Some(memory_mib * MIB);
+        info
+    }
+
+    fn workload() -> Workload {
+        Workload {
+            kind: "Deployment".to_string(),
+            name: "web".to_string(),
+        }
+    }
+
+    fn history(
+        container: &str,
+        cpu_millicores: &[u64],
+        memory_mib: &[u64],
+    ) -> (SampleKey, UsageHistory) {
+        let key = (
+            "prod".to_string(),
+            "shop".to_string(),
+            workload(),
+            container.to_string(),
+        );
+        let usage = UsageHistory {
+            cpu_millicores: cpu_millicores.to_vec(),
+            memory_bytes: memory_mib.iter().map(|mib| mib * MIB).collect(),
+        };
+        (key, usage)
+    }
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let values: Vec<u64> = (1..=10).rev().collect();
+        assert_eq!(percentile_of(values.clone(), 90.0), Some(9));
+        assert_eq!(percentile_of(values.clone(), 91.0), Some(10));
+        assert_eq!(percentile_of(values.clone(), 50.0), Some(5));
+        assert_eq!(percentile_of(values.clone(), 100.0), Some(10));
+        assert_eq!(percentile_of(values, 1.0), Some(1));
+        assert_eq!(percentile_of(vec![7], 90.0), Some(7));
+        assert_eq!(percentile_of(Vec::new(), 90.0), None);
+    }
+
+    #[test]
+    fn headroom_rounds_up() {
+        assert_eq!(with_headroom(100, 0.15), 115);
+        assert_eq!(with_headroom(1, 0.15), 2);
+        assert_eq!(with_headroom(0, 0.15), 0);
+        assert_eq!(with_headroom(200, 0.0), 200);
+        assert_eq!(with_headroom(3, 0.5), 5);
+        assert_eq!(round_up_to_mib(0), MIB);
+        assert_eq!(round_up_to_mib(MIB), MIB);
+        assert_eq!(round_up_to_mib(MIB + 1), 2 * MIB);
+    }
+
+    #[test]
+    fn withholds_containers_below_the_minimum_sample_count() {
+        let pods = [pod("web-1", 100, 100), pod("web-2", 120, 110)];
+        let (recommendations, withheld) = recommend(
+            &pods,
+            &BTreeMap::new(),
+            90.0,
+            0.0,
+            3,
+            DisplayUnits::default(),
+        );
+        assert!(recommendations.is_empty());
+        assert_eq!(
+            withheld,
+            [WithheldRecommendation {
+                cluster: "prod".to_string(),
+                namespace: "shop".to_string(),
+                workload: workload(),
+                container: "app".to_string(),
+                samples: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn recommends_from_current_usage_and_history() {
+        let pods = [pod("web-1", 100, 100), pod("web-2", 120, 110)];
+        let history = BTreeMap::from([
+            history("app", &[300, 80], &[200, 90]),
+            // Containers that are not part of the queried pods are ignored.
+            history("sidecar", &[1000], &[1000]),
+        ]);
+        let (recommendations, withheld) =
+            recommend(&pods, &history, 100.0, 0.25, 3, DisplayUnits::default());
+        assert!(withheld.is_empty());
+        assert_eq!(recommendations.len(), 1);
+        let recommendation = &recommendations[0];
+        assert_eq!(recommendation.replicas, 2);
+        assert_eq!(recommendation.samples, 4);
+        assert_eq!(recommendation.recommended_cpu_millicores, 375);
+        assert_eq!(recommendation.recommended_cpu, "375m");
+        assert_eq!(recommendation.recommended_memory_bytes, 250 * MIB);
+        assert_eq!(recommendation.recommended_memory, "250Mi");
+        assert_eq!(recommendation.cpu_savings_millicores, (500 - 375) * 2);
+        assert_eq!(
+            recommendation.memory_savings_bytes,
+            (512 - 250) * MIB as i64 * 2
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use k8s_openapi::api::core::v1::Pod;
+
+// Field selectors the apiserver accepts for pods; anything else is rejected with a 400 upfront.
+const POD_SELECTABLE_FIELDS: &[&str] = &[
+    "metadata.name",
+    "metadata.namespace",
+    "spec.nodeName",
+    "spec.restartPolicy",
+    "spec.schedulerName",
+    "spec.serviceAccountName",
+    "spec.hostNetwork",
+    "status.phase",
+    "status.podIP",
+    "status.podIPs",
+    "status.nominatedNodeName",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelRequirement {
+    Exists(String),
+    NotExists(String),
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelSelector {
+    pub requirements: Vec<LabelRequirement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldRequirement {
+    pub field: String,
+    pub negated: bool,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldSelector {
+    pub requirements: Vec<FieldRequirement>,
+}
+
+impl LabelSelector {
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    pub fn and(mut self, other: LabelSelector) -> LabelSelector {
+        self.requirements.extend(other.requirements);
+        self
+    }
+
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements
+            .iter()
+            .all(|requirement| match requirement {
+                LabelRequirement::Exists(key) => labels.contains_key(key),
+                LabelRequirement::NotExists(key) => !labels.contains_key(key),
+                LabelRequirement::Equals(key, value) => labels.get(key) == Some(value),
+                LabelRequirement::NotEquals(key, value) => labels.get(key) != Some(value),
+                LabelRequirement::In(key, values) => {
+                    labels.get(key).is_some_and(|v| values.contains(v))
+                }
+                LabelRequirement::NotIn(key, values) => {
+                    !labels.get(key).is_some_and(|v| values.contains(v))
+                }
+            })
+    }
+}
+
+impl FieldSelector {
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    // Evaluates the selector the way the apiserver would, for pods that are served from the cache.
+    pub fn matches_pod(&self, pod: &Pod) -> bool {
+        self.requirements.iter().all(|requirement| {
+            let found = pod_field_values(pod, &requirement.field).contains(&requirement.value);
+            found != requirement.negated
+        })
+    }
+}
+
+fn pod_field_values(pod: &Pod, field: &str) -> Vec<String> {
+    let spec = pod.spec.as_ref();
+    let status = pod.status.as_ref();
+    let value = match field {
+        "metadata.name" => pod.metadata.name.clone(),
+        "metadata.namespace" => pod.metadata.namespace.clone(),
+        "spec.nodeName" => spec.and_then(|s| s.node_name.clone()),
+        "spec.restartPolicy" => spec.and_then(|s| s.restart_policy.clone()),
+        "spec.schedulerName" => spec.and_then(|s| s.scheduler_name.clone()),
+        "spec.serviceAccountName" => spec.and_then(|s| s.service_account_name.clone()),
+        "spec.hostNetwork" => Some(
+            spec.and_then(|s| s.host_network)
+                .unwrap_or(false)
+                .to_string(),
+        ),
+        "status.phase" => status.and_then(|s| s.phase.clone()),
+        "status.podIP" => status.and_then(|s| s.pod_ip.clone()),
+        "status.nominatedNodeName" => status.and_then(|s| s.nominated_node_name.clone()),
+        "status.podIPs" => {
+            return status
+                .and_then(|s| s.pod_ips.as_ref())
+                .map(|ips| ips.iter().filter_map(|ip| ip.ip.clone()).collect())
+                .unwrap_or_default()
+        }
+        _ => None,
+    };
+    vec![value.unwrap_or_default()]
+}
+
+impl FromStr for LabelSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let requirements = split_requirements(s)?
+            .into_iter()
+            .map(parse_label_requirement)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(LabelSelector { requirements })
+    }
+}
+
+impl FromStr for FieldSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let requirements = split_requirements(s)?
+            .into_iter()
+            .map(parse_field_requirement)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(FieldSelector { requirements })
+    }
+}
+
+impl fmt::Display for LabelRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelRequirement::Exists(key) => write!(f, "{}", key),
+            LabelRequirement::NotExists(key) => write!(f, "!{}", key),
+            LabelRequirement::Equals(key, value) => write!(f, "{}={}", key, value),
+            LabelRequirement::NotEquals(key, value) => write!(f, "{}!={}", key, value),
+            LabelRequirement::In(key, values) => write!(f, "{} in ({})", key, values.join(",")),
+            LabelRequirement::NotIn(key, values) => {
+                write!(f, "{} notin ({})", key, values.join(","))
+            }
+        }
+    }
+}
+
+impl fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.requirements.iter().map(|r| r.to_string()).collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+impl fmt::Display for FieldRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = if self.negated { "!=" } else { "=" };
+        write!(f, "{}{}{}", self.field, op, self.value)
+    }
+}
+
+impl fmt::Display for FieldSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.requirements.iter().map(|r| r.to_string()).collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+pub fn validate_label_key(key: &str) -> anyhow::Result<()> {
+    let name = match key.split_once('/') {
+        Some((prefix, name)) => {
+            let valid_prefix = !prefix.is_empty()
+                && prefix.len() <= 253
+                && prefix.split('.').all(|segment| {
+                    !segment.is_empty()
+                        && segment
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                        && !segment.starts_with('-')
+                        && !segment.ends_with('-')
+                });
+            if !valid_prefix {
+                bail!("invalid label key prefix in {:?}", key);
+            }
+            name
+        }
+        None => key,
+    };
+    if name.is_empty() || !is_label_name(name) {
+        bail!("invalid label key {:?}", key);
+    }
+    Ok(())
+}
+
+pub fn validate_label_value(value: &str) -> anyhow::Result<()> {
+    if !value.is_empty() && !is_label_name(value) {
+        bail!("invalid label value {:?}", value);
+    }
+    Ok(())
+}
+
+fn is_label_name(s: &str) -> bool {
+    let alphanumeric_edges = s.chars().next().is_some_and(|c| c.is_ascii_alphanumeric())
+        && s.chars().last().is_some_and(|c| c.is_ascii_alphanumeric());
+    s.len() <= 63
+        && alphanumeric_edges
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn split_requirements(s: &str) -> anyhow::Result<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => bail!("unbalanced parenthesis in selector {:?}", s),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        bail!("unbalanced parenthesis in selector {:?}", s);
+    }
+    parts.push(s[start..].trim());
+    if parts == [""] {
+        return Ok(Vec::new());
+    }
+    if parts.iter().any(|p| p.is_empty()) {
+        bail!("empty requirement in selector {:?}", s);
+    }
+    Ok(parts)
+}
+
+fn parse_label_requirement(s: &str) -> anyhow::Result<LabelRequirement> {
+    if let Some(key) = s.strip_prefix('!') {
+        let key = key.trim();
+        validate_label_key(key)?;
+        return Ok(LabelRequirement::NotExists(key.to_string()));
+    }
+    let key_end = s
+        .find(|c: char| c.is_whitespace() || c == '=' || c == '!' || c == '(')
+        .unwrap_or(s.len());
+    let key = &s[..key_end];
+    validate_label_key(key)?;
+    let key = key.to_string();
+    let rest = s[key_end..].trim_start();
+
+    if rest.is_empty() {
+        return Ok(LabelRequirement::Exists(key));
+    }
+    if let Some(value) = rest.strip_prefix("!=") {
+        let value = value.trim();
+        validate_label_value(value)?;
+        return Ok(LabelRequirement::NotEquals(key, value.to_string()));
+    }
+    if let Some(value) = rest.strip_prefix("==").or_else(|| rest.strip_prefix('=')) {
+        let value = value.trim();
+        validate_label_value(value)?;
+        return Ok(LabelRequirement::Equals(key, value.to_string()));
+    }
+    if let Some(values) = rest.strip_prefix("notin") {
+        return Ok(LabelRequirement::NotIn(key, parse_value_set(values)?));
+    }
+    if let Some(values) = rest.strip_prefix("in") {
+        return Ok(LabelRequirement::In(key, parse_value_set(values)?));
+    }
+    Err(anyhow!("unsupported operator in label requirement {:?}", s))
+}
+
+fn parse_value_set(s: &str) -> anyhow::Result<Vec<String>> {
+    let inner = s
+        .trim()
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("expected a parenthesized value list, got {:?}", s.trim()))?;
+    let values: Vec<String> = inner.split(',').map(|v| v.trim().to_string()).collect();
+    for value in &values {
+        validate_label_value(value)?;
+    }
+    Ok(values)
+}
+
+fn parse_field_requirement(s: &str) -> anyhow::Result<FieldRequirement> {
+    let (field, negated, value) = if let Some((field, value)) = s.split_once("!=") {
+        (field, true, value)
+    } else if let Some((field, value)) = s.split_once("==") {
+        (field, false, value)
+    } else if let Some((field, value)) = s.split_once('=') {
+        (field, false, value)
+    } else {
+        bail!("field requirement {:?} must use =, == or !=", s);
+    };
+    let field = field.trim();
+    if !POD_SELECTABLE_FIELDS.contains(&field) {
+        bail!(
+            "field {:?} is not selectable for pods, supported fields are: {}",
+            field,
+            POD_SELECTABLE_FIELDS.join(", ")
+        );
+    }
+    Ok(FieldRequirement {
+        field: field.to_string(),
+        negated,
+        value: value.trim().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodIP, PodSpec, PodStatus};
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_every_label_operator() {
+        let selector: LabelSelector =
+            "app, !legacy, tier=web, env==prod, track!=canary, team in (a, b), zone notin (z1)"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            selector.requirements,
+            vec![
+                LabelRequirement::Exists("app".to_string()),
+                LabelRequirement::NotExists("legacy".to_string()),
+                LabelRequirement::Equals("tier".to_string(), "web".to_string()),
+                LabelRequirement::Equals("env".to_string(), "prod".to_string()),
+                LabelRequirement::NotEquals("track".to_string(), "canary".to_string()),
+                LabelRequirement::In("team".to_string(), vec!["a".to_string(), "b".to_string()]),
+                LabelRequirement::NotIn("zone".to_string(), vec!["z1".to_string()]),
+            ]
+        );
+        assert!("".parse::<LabelSelector>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_label_selectors() {
+        for selector in [
+            "app,",
+            "team in (a",
+            "team in a,b)",
+            "team in (a b)",
+            "tier=web!",
+            "team ~ a",
+            "-app=x",
+            "Example.com/app=x",
+        ] {
+            assert!(
+                selector.parse::<LabelSelector>().is_err(),
+                "{:?} was accepted",
+                selector
+            );
+        }
+    }
+
+    #[test]
+    fn label_selector_display_round_trips() {
+        let selector: LabelSelector =
+            "example.com/app,!legacy,tier=web,track!=canary,team in (a,b),zone notin (z1)"
+                .parse()
+                .unwrap();
+        let rendered = selector.to_string();
+        assert_eq!(
+            rendered,
+            "example.com/app,!legacy,tier=web,track!=canary,team in (a,b),zone notin (z1)"
+        );
+        assert_eq!(rendered.parse::<LabelSelector>().unwrap(), selector);
+    }
+
+    #[test]
+    fn label_selector_matches_labels() {
+        let selector: LabelSelector =
+            "app,!legacy,tier=web,track!=canary,team in (a,b),zone notin (z1)"
+                .parse()
+                .unwrap();
+        let matching = labels(&[("app", "shop"), ("tier", "web"), ("team", "a")]);
+        assert!(selector.matches(&matching));
+
+        let mut legacy = matching.clone();
+        legacy.insert("legacy".to_string(), "true".to_string());
+        assert!(!selector.matches(&legacy));
+        assert!(!selector.matches(&labels(&[("app", "shop"), ("tier", "web")])));
+        let mut canary = matching.clone();
+        canary.insert("track".to_string(), "canary".to_string());
+        assert!(!selector.matches(&canary));
+        let mut excluded_zone = matching.clone();
+        excluded_zone.insert("zone".to_string(), "z1".to_string());
+        assert!(!selector.matches(&excluded_zone));
+        assert!(LabelSelector::default().matches(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn parses_field_selectors() {
+        let selector: FieldSelector =
+            "spec.nodeName=node-1, status.phase!=Failed, metadata.name==web"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            selector.requirements,
+            vec![
+                FieldRequirement {
+                    field: "spec.nodeName".to_string(),
+                    negated: false,
+                    value: "node-1".to_string(),
+                },
+                FieldRequirement {
+                    field: "status.phase".to_string(),
+                    negated: true,
+                    value: "Failed".to_string(),
+                },
+                FieldRequirement {
+                    field: "metadata.name".to_string(),
+                    negated: false,
+                    value: "web".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            selector.to_string(),
+            "spec.nodeName=node-1,status.phase!=Failed,metadata.name=web"
+        );
+        assert!("metadata.labels=x".parse::<FieldSelector>().is_err());
+        assert!("spec.nodeName".parse::<FieldSelector>().is_err());
+    }
+
+    #[test]
+    fn field_selector_matches_pods() {
+        let pod = Pod {
+            metadata: kube::api::ObjectMeta {
+                name: Some("web".to_string()),
+                namespace: Some("shop".to_string()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: Some("node-1".to_string()),
+                ..Default::default()
+            }),
+            status: Some(PodStatus {
+                phase: Some("Running".to_string()),
+                pod_ips: Some(vec![
+                    PodIP {
+                        ip: Some("10.0.0.1".to_string()),
+                    },
+                    PodIP {
+                        ip: Some("fd00::1".to_string()),
+                    },
+                ]),
+                ..Default::default()
+            }),
+        };
+        let matches = |selector: &str| selector.parse::<FieldSelector>().unwrap().matches_pod(&pod);
+        assert!(matches("spec.nodeName=node-1,metadata.namespace=shop"));
+        assert!(matches("status.phase!=Pending"));
+        assert!(matches("status.podIPs=fd00::1"));
+        assert!(matches("spec.hostNetwork=false"));
+        assert!(!matches("spec.nodeName=node-2"));
+        assert!(!matches("metadata.name!=web"));
+        // Unset fields compare as empty strings, like the apiserver's field sets.
+        assert!(matches("spec.schedulerName="));
+    }
+}
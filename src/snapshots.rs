@@ -0,0 +1,677 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::extract::{Json, Query, State};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{ApiError, QueryError};
+use crate::workloads::Workload;
+use crate::{
+    build_pod_query, get_pods_info, AppState, PodComputeInfo, PodComputeInfoRequestBody, PodsInfo,
+};
+
+// Only what the snapshot endpoint returns is stored: no labels, raw quantities or display values.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY,
+    taken_at TEXT NOT NULL,
+    pod_count INTEGER NOT NULL,
+    errors TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS snapshots_taken_at ON snapshots (taken_at);
+CREATE TABLE IF NOT EXISTS snapshot_pods (
+    id INTEGER PRIMARY KEY,
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id) ON DELETE CASCADE,
+    cluster TEXT NOT NULL,
+    namespace TEXT NOT NULL,
+    name TEXT NOT NULL,
+    node TEXT NOT NULL,
+    maintainer TEXT NOT NULL,
+    workload_kind TEXT NOT NULL,
+    workload_name TEXT NOT NULL,
+    requested_cpu_millicores INTEGER NOT NULL,
+    requested_memory_bytes INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS snapshot_pods_snapshot_id ON snapshot_pods (snapshot_id);
+CREATE INDEX IF NOT EXISTS snapshot_pods_workload
+    ON snapshot_pods (cluster, namespace, workload_kind, workload_name);
+CREATE TABLE IF NOT EXISTS snapshot_containers (
+    pod_id INTEGER NOT NULL REFERENCES snapshot_pods (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    requested_cpu_millicores INTEGER,
+    requested_memory_bytes INTEGER,
+    limit_cpu_millicores INTEGER,
+    limit_memory_bytes INTEGER,
+    used_cpu_millicores INTEGER,
+    used_memory_bytes INTEGER
+);
+CREATE INDEX IF NOT EXISTS snapshot_containers_pod_id ON snapshot_containers (pod_id);
+";
+
+// Snapshots of every pod the service can see, kept in SQLite.
+#[derive(Clone)]
+pub struct SnapshotStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct SnapshotParams {
+    at: Option<String>,
+    cluster: Option<String>,
+    namespace: Option<String>,
+    maintainer: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SnapshotsResponse {
+    snapshots: Vec<SnapshotInfo>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SnapshotInfo {
+    id: i64,
+    taken_at: String,
+    pod_count: u64,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SnapshotResponse {
+    id: i64,
+    taken_at: String,
+    errors: Value,
+    requested_cpu_millicores: u64,
+    requested_memory_bytes: u64,
+    pods: Vec<SnapshotPod>,
+}
+
+// Requests are the pod totals of app containers and sidecars, as in the pods endpoint.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SnapshotPod {
+    cluster: String,
+    namespace: String,
+    name: String,
+    node_name: String,
+    maintainer: String,
+    workload: Workload,
+    requested_cpu_millicores: u64,
+    requested_memory_bytes: u64,
+    containers: Vec<SnapshotContainer>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SnapshotContainer {
+    name: String,
+    kind: String,
+    requested_cpu_millicores: Option<u64>,
+    requested_memory_bytes: Option<u64>,
+    limit_cpu_millicores: Option<u64>,
+    limit_memory_bytes: Option<u64>,
+    used_cpu_millicores: Option<u64>,
+    used_memory_bytes: Option<u64>,
+}
+
+// One container's usage over a series of snapshots.
+#[derive(Debug, Default, PartialEq)]
+pub struct UsageHistory {
+    pub cpu_millicores: Vec<u64>,
+    pub memory_bytes: Vec<u64>,
+}
+
+impl SnapshotStore {
+    pub fn open(path: &Path) -> anyhow::Result<SnapshotStore> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("opening snapshot database {}", path.display()))?;
+        SnapshotStore::new(connection)
+    }
+
+    // Pruned pages are handed back to the file system by incremental vacuuming, which a database created without it
+    // only supports after one full VACUUM.
+    fn new(connection: Connection) -> anyhow::Result<SnapshotStore> {
+        connection.execute_batch("PRAGMA foreign_keys = ON; PRAGMA auto_vacuum = INCREMENTAL;")?;
+        let auto_vacuum: i64 = connection.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+        if auto_vacuum != 2 {
+            connection.execute_batch("VACUUM;")?;
+        }
+        connection.execute_batch(SCHEMA)?;
+        Ok(SnapshotStore {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    // Each workload is read under its own lock, so a long read does not hold up captures and pruning.
+    pub async fn usage_history(
+        &self,
+        cluster: &str,
+        namespace: &str,
+        workload: &Workload,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<BTreeMap<String, UsageHistory>> {
+        let (cluster, namespace, workload) =
+            (cluster.to_string(), namespace.to_string(), workload.clone());
+        let since = timestamp(since);
+        self.with_connection(move |connection| {
+            usage_history(connection, &cluster, &namespace, &workload, &since)
+        })
+        .await
+    }
+
+    async fn with_connection<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || f(&mut connection.lock().unwrap())).await?
+    }
+}
+
+// Captures a cluster-wide snapshot every interval and drops snapshots older than the retention.
+pub fn spawn(state: AppState, store: SnapshotStore) {
+    let interval = Duration::from_secs(state.config.snapshot_interval_seconds);
+    let retention = chrono::Duration::days(state.config.snapshot_retention_days as i64);
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            if let Err(e) = capture(&state, &store).await {
+                eprintln!("Error capturing snapshot: {:#}", e);
+            }
+            let cutoff = timestamp(Utc::now() - retention);
+            let pruned = store
+                .with_connection(move |connection| prune(connection, &cutoff))
+                .await;
+            if let Err(e) = pruned {
+                eprintln!("Error pruning snapshots: {:#}", e);
+            }
+        }
+    });
+}
+
+async fn capture(state: &AppState, store: &SnapshotStore) -> anyhow::Result<()> {
+    let request_body = PodComputeInfoRequestBody {
+        all_namespaces: Some(true),
+        ..Default::default()
+    };
+    let query = build_pod_query(state, &request_body)?;
+    let PodsInfo { pods, errors, .. } = get_pods_info(state, &query).await?;
+    let taken_at = timestamp(Utc::now());
+    store
+        .with_connection(move |connection| {
+            insert(connection, &taken_at, &pods, &errors)?;
+            Ok(())
+        })
+        .await
+}
+
+fn insert(
+    connection: &mut Connection,
+    taken_at: &str,
+    pods: &[PodComputeInfo],
+    errors: &[QueryError],
+) -> anyhow::Result<i64> {
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO snapshots (taken_at, pod_count, errors) VALUES (?1, ?2, ?3)",
+        params![taken_at, pods.len() as i64, serde_json::to_string(errors)?],
+    )?;
+    let snapshot_id = transaction.last_insert_rowid();
+    {
+        let mut insert_pod = transaction.prepare(
+            "INSERT INTO snapshot_pods (snapshot_id, cluster, namespace, name, node, maintainer, workload_kind, \
+             workload_name, requested_cpu_millicores, requested_memory_bytes) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        let mut insert_container = transaction.prepare(
+            "INSERT INTO snapshot_containers (pod_id, name, kind, requested_cpu_millicores, \
+             requested_memory_bytes, limit_cpu_millicores, limit_memory_bytes, used_cpu_millicores, \
+             used_memory_bytes) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?;
+        for pod in pods {
+            let pod_id = insert_pod.insert(params![
+                snapshot_id,
+                pod.cluster,
+                pod.namespace,
+                pod.name,
+                pod.node_name,
+                pod.maintainer,
+                pod.workload.kind,
+                pod.workload.name,
+                pod.totals.requested_cpu_millicores as i64,
+                pod.totals.requested_memory_bytes as i64,
+            ])?;
+            for container in &pod.containers {
+                let resources = &container.compute_resources;
+                insert_container.execute(params![
+                    pod_id,
+                    container.name,
+                    container.kind.as_str(),
+                    resources.requested_cpu_millicores.map(|v| v as i64),
+                    resources.requested_memory_bytes.map(|v| v as i64),
+                    resources.limit_cpu_millicores.map(|v| v as i64),
+                    resources.limit_memory_bytes.map(|v| v as i64),
+                    container.used_cpu_millicores.map(|v| v as i64),
+                    container.used_memory_bytes.map(|v| v as i64),
+                ])?;
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(snapshot_id)
+}
+
+// Deleting cascades to pods and containers; the freed pages are then returned to the file system.
+fn prune(connection: &mut Connection, cutoff: &str) -> anyhow::Result<usize> {
+    let pruned = connection.execute("DELETE FROM snapshots WHERE taken_at < ?1", [cutoff])?;
+    if pruned > 0 {
+        // The pragma frees one page per step, so it has to be stepped to the end.
+        let mut vacuum = connection.prepare("PRAGMA incremental_vacuum")?;
+        let mut rows = vacuum.query([])?;
+        while rows.next()?.is_some() {}
+    }
+    Ok(pruned)
+}
+
+const USAGE_HISTORY: &str =
+    "SELECT c.name, c.used_cpu_millicores, c.used_memory_bytes FROM snapshot_pods p \
+    JOIN snapshots s ON s.id = p.snapshot_id JOIN snapshot_containers c ON c.pod_id = p.id \
+    WHERE p.cluster = ?1 AND p.namespace = ?2 AND p.workload_kind = ?3 AND p.workload_name = ?4 \
+    AND s.taken_at >= ?5 AND c.kind != 'init'";
+
+// Usage of the app containers and sidecars of one workload in the snapshots taken since `since`, per container.
+// Rows are folded into the samples as they are read.
+fn usage_history(
+    connection: &Connection,
+    cluster: &str,
+    namespace: &str,
+    workload: &Workload,
+    since: &str,
+) -> anyhow::Result<BTreeMap<String, UsageHistory>> {
+    let mut select = connection.prepare_cached(USAGE_HISTORY)?;
+    let mut rows = select.query(params![
+        cluster,
+        namespace,
+        workload.kind,
+        workload.name,
+        since
+    ])?;
+    let mut history: BTreeMap<String, UsageHistory> = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        let cpu_millicores: Option<u64> = row.get(1)?;
+        let memory_bytes: Option<u64> = row.get(2)?;
+        if cpu_millicores.is_none() && memory_bytes.is_none() {
+            continue;
+        }
+        let container = row.get_ref(0)?.as_str()?;
+        if !history.contains_key(container) {
+            history.insert(container.to_string(), UsageHistory::default());
+        }
+        let usage = history.get_mut(container).unwrap();
+        usage.cpu_millicores.extend(cpu_millicores);
+        usage.memory_bytes.extend(memory_bytes);
+    }
+    Ok(history)
+}
+
+// RFC 3339 in UTC with a fixed width, so timestamps compare correctly as text.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn snapshot_store(state: &AppState) -> Result<&SnapshotStore, ApiError> {
+    state.snapshots.as_ref().ok_or_else(|| {
+        ApiError::NotFound(
+            "snapshots are disabled; set snapshot_database to enable them".to_string(),
+        )
+    })
+}
+
+pub async fn get_snapshots(
+    State(state): State<AppState>,
+) -> Result<Json<SnapshotsResponse>, ApiError> {
+    let snapshots = snapshot_store(&state)?
+        .with_connection(|connection| list(connection))
+        .await?;
+    Ok(Json(SnapshotsResponse { snapshots }))
+}
+
+fn list(connection: &Connection) -> anyhow::Result<Vec<SnapshotInfo>> {
+    let mut select =
+        connection.prepare("SELECT id, taken_at, pod_count FROM snapshots ORDER BY taken_at")?;
+    let snapshots = select
+        .query_map([], |row| {
+            Ok(SnapshotInfo {
+                id: row.get(0)?,
+                taken_at: row.get(1)?,
+                pod_count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(snapshots)
+}
+
+// Returns the last snapshot taken at or before `at` (the latest one without it), optionally narrowed to one
+// cluster, namespace or maintainer.
+pub async fn get_snapshot(
+    State(state): State<AppState>,
+    Query(params): Query<SnapshotParams>,
+) -> Result<Json<SnapshotResponse>, ApiError> {
+    let at = match &params.at {
+        Some(at) => DateTime::parse_from_rfc3339(at)
+            .map_err(|e| ApiError::bad_request(format!("invalid at: {}", e)))?
+            .with_timezone(&Utc),
+        None => Utc::now(),
+    };
+    let at = timestamp(at);
+    let snapshot = snapshot_store(&state)?
+        .with_connection(move |connection| snapshot_at(connection, &at, &params))
+        .await?;
+    snapshot.map(Json).ok_or_else(|| {
+        ApiError::NotFound("no snapshot was taken at or before that time".to_string())
+    })
+}
+
+fn snapshot_at(
+    connection: &Connection,
+    at: &str,
+    params: &SnapshotParams,
+) -> anyhow::Result<Option<SnapshotResponse>> {
+    let Some((id, taken_at, errors)) = connection
+        .query_row(
+            "SELECT id, taken_at, errors FROM snapshots WHERE taken_at <= ?1 \
+             ORDER BY taken_at DESC LIMIT 1",
+            [at],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?)),
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let mut select_pods = connection.prepare(
+        "SELECT id, cluster, namespace, name, node, maintainer, workload_kind, workload_name, \
+         requested_cpu_millicores, requested_memory_bytes FROM snapshot_pods \
+         WHERE snapshot_id = ?1 AND (?2 IS NULL OR cluster = ?2) \
+         AND (?3 IS NULL OR namespace = ?3) AND (?4 IS NULL OR maintainer = ?4) \
+         ORDER BY cluster, namespace, name",
+    )?;
+    let mut select_containers = connection.prepare(
+        "SELECT name, kind, requested_cpu_millicores, requested_memory_bytes, limit_cpu_millicores, \
+         limit_memory_bytes, used_cpu_millicores, used_memory_bytes FROM snapshot_containers \
+         WHERE pod_id = ?1 ORDER BY rowid",
+    )?;
+    let pods = select_pods
+        .query_map(
+            params![id, params.cluster, params.namespace, params.maintainer],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    SnapshotPod {
+                        cluster: row.get(1)?,
+                        namespace: row.get(2)?,
+                        name: row.get(3)?,
+                        node_name: row.get(4)?,
+                        maintainer: row.get(5)?,
+                        workload: Workload {
+                            kind: row.get(6)?,
+                            name: row.get(7)?,
+                        },
+                        requested_cpu_millicores: row.get(8)?,
+                        requested_memory_bytes: row.get(9)?,
+                        containers: Vec::new(),
+                    },
+                ))
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut snapshot = SnapshotResponse {
+        id,
+        taken_at,
+        errors: serde_json::from_str(&errors)?,
+        requested_cpu_millicores: 0,
+        requested_memory_bytes: 0,
+        pods: Vec::with_capacity(pods.len()),
+    };
+    for (pod_id, mut pod) in pods {
+        pod.containers = select_containers
+            .query_map([pod_id], |row| {
+                Ok(SnapshotContainer {
+                    name: row.get(0)?,
+                    kind: row.get(1)?,
+                    requested_cpu_millicores: row.get(2)?,
+                    requested_memory_bytes: row.get(3)?,
+                    limit_cpu_millicores: row.get(4)?,
+                    limit_memory_bytes: row.get(5)?,
+                    used_cpu_millicores: row.get(6)?,
+                    used_memory_bytes: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        snapshot.requested_cpu_millicores += pod.requested_cpu_millicores;
+        snapshot.requested_memory_bytes += pod.requested_memory_bytes;
+        snapshot.pods.push(pod);
+    }
+    Ok(Some(snapshot))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> SnapshotStore {
+        SnapshotStore::new(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn pod(namespace: &str, name: &str, maintainer: &str) -> PodComputeInfo {
+        let mut info = crate::tests::pod_info(
+            "prod",
+            namespace,
+            name,
+            vec![
+                crate::tests::spec_container("app", "200m", "128Mi"),
+                crate::tests::spec_container("proxy", "50m", "32Mi"),
+            ],
+        );
+        info.maintainer = maintainer.to_string();
+        info.node_name = "node-1".to_string();
+        info.containers[0].used_cpu_millicores = Some(120);
+        info.containers[0].used_memory_bytes = Some(100 * 1024 * 1024);
+        info
+    }
+
+    fn with_connection<T>(store: &SnapshotStore, f: impl FnOnce(&mut Connection) -> T) -> T {
+        f(&mut store.connection.lock().unwrap())
+    }
+
+    fn count(connection: &Connection, table: &str) -> i64 {
+        connection
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn captures_the_fields_the_endpoint_returns() {
+        let store = store();
+        let pods = [pod("shop", "web", "team-a"), pod("shop", "api", "team-b")];
+        let errors = [QueryError {
+            cluster: "staging".to_string(),
+            namespace: None,
+            status: 503,
+            detail: "unreachable".to_string(),
+        }];
+        with_connection(&store, |connection| {
+            insert(connection, "2026-10-01T10:00:00Z", &pods, &errors).unwrap();
+            let snapshot = snapshot_at(connection, "2026-10-01T10:00:00Z", &Default::default())
+                .unwrap()
+                .unwrap();
+            assert_eq!(snapshot.taken_at, "2026-10-01T10:00:00Z");
+            assert_eq!(snapshot.errors[0]["cluster"], "staging");
+            assert_eq!(snapshot.requested_cpu_millicores, 500);
+            assert_eq!(snapshot.requested_memory_bytes, 320 * 1024 * 1024);
+            let names: Vec<&str> = snapshot.pods.iter().map(|pod| pod.name.as_str()).collect();
+            assert_eq!(names, ["api", "web"]);
+            let web = &snapshot.pods[1];
+            assert_eq!(web.maintainer, "team-a");
+            assert_eq!(web.node_name, "node-1");
+            assert_eq!(web.containers.len(), 2);
+            let app = &web.containers[0];
+            assert_eq!(app.name, "app");
+            assert_eq!(app.kind, "app");
+            assert_eq!(app.requested_cpu_millicores, Some(200));
+            assert_eq!(app.limit_cpu_millicores, None);
+            assert_eq!(app.used_cpu_millicores, Some(120));
+            assert_eq!(web.containers[1].used_cpu_millicores, None);
+            assert_eq!(list(connection).unwrap()[0].pod_count, 2);
+        });
+    }
+
+    #[test]
+    fn filters_a_snapshot() {
+        let store = store();
+        let pods = [
+            pod("shop", "web", "team-a"),
+            pod("billing", "api", "team-b"),
+        ];
+        with_connection(&store, |connection| {
+            insert(connection, "2026-10-01T10:00:00Z", &pods, &[]).unwrap();
+            let filtered = |params: SnapshotParams| {
+                snapshot_at(connection, "2026-10-01T10:00:00Z", &params)
+                    .unwrap()
+                    .unwrap()
+                    .pods
+                    .len()
+            };
+            let maintainer = |m: &str| SnapshotParams {
+                maintainer: Some(m.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(filtered(maintainer("team-b")), 1);
+            assert_eq!(filtered(maintainer("team-c")), 0);
+            let namespace = SnapshotParams {
+                namespace: Some("shop".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(filtered(namespace), 1);
+            let cluster = SnapshotParams {
+                cluster: Some("staging".to_string()),
+                ..Default::default()
+            };
+            assert_eq!(filtered(cluster), 0);
+        });
+    }
+
+    #[test]
+    fn looks_up_the_last_snapshot_at_or_before_a_time() {
+        let store = store();
+        with_connection(&store, |connection| {
+            let first = insert(connection, "2026-10-01T10:00:00Z", &[], &[]).unwrap();
+            let second = insert(
+                connection,
+                "2026-10-01T11:00:00Z",
+                &[pod("shop", "web", "a")],
+                &[],
+            )
+            .unwrap();
+            let id_at = |at: &str| {
+                snapshot_at(connection, at, &Default::default())
+                    .unwrap()
+                    .map(|snapshot| snapshot.id)
+            };
+            assert_eq!(id_at("2026-10-01T09:59:59Z"), None);
+            assert_eq!(id_at("2026-10-01T10:00:00Z"), Some(first));
+            assert_eq!(id_at("2026-10-01T10:59:59Z"), Some(first));
+            assert_eq!(id_at("2026-10-01T11:00:00Z"), Some(second));
+            assert_eq!(id_at("2026-10-02T00:00:00Z"), Some(second));
+        });
+    }
+
+    #[test]
+    fn prunes_old_snapshots_with_their_pods() {
+        let store = store();
+        with_connection(&store, |connection| {
+            let pods = [pod("shop", "web", "a")];
+            insert(connection, "2026-09-01T10:00:00Z", &pods, &[]).unwrap();
+            insert(connection, "2026-09-02T10:00:00Z", &pods, &[]).unwrap();
+            let kept = insert(connection, "2026-10-01T10:00:00Z", &pods, &[]).unwrap();
+            assert_eq!(prune(connection, "2026-09-15T00:00:00Z").unwrap(), 2);
+            assert_eq!(prune(connection, "2026-09-15T00:00:00Z").unwrap(), 0);
+            let remaining: Vec<i64> = list(connection).unwrap().iter().map(|s| s.id).collect();
+            assert_eq!(remaining, [kept]);
+            assert_eq!(count(connection, "snapshot_pods"), 1);
+            assert_eq!(count(connection, "snapshot_containers"), 2);
+            let free_pages: i64 = connection
+                .query_row("PRAGMA freelist_count", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(free_pages, 0);
+        });
+    }
+
+    #[test]
+    fn reads_the_usage_history_of_one_workload() {
+        let store = store();
+        with_connection(&store, |connection| {
+            let pods = [pod("shop", "web", "a"), pod("shop", "api", "a")];
+            insert(connection, "2026-10-01T10:00:00Z", &pods, &[]).unwrap();
+            insert(connection, "2026-10-01T11:00:00Z", &pods, &[]).unwrap();
+            insert(connection, "2026-10-01T12:00:00Z", &pods, &[]).unwrap();
+            let web = Workload {
+                kind: "Pod".to_string(),
+                name: "web".to_string(),
+            };
+            let history =
+                usage_history(connection, "prod", "shop", &web, "2026-10-01T11:00:00Z").unwrap();
+            // Only the app container has usage; the proxy without any is left out, as is the api pod.
+            assert_eq!(history.keys().collect::<Vec<_>>(), ["app"]);
+            assert_eq!(
+                history["app"],
+                UsageHistory {
+                    cpu_millicores: vec![120, 120],
+                    memory_bytes: vec![100 * 1024 * 1024; 2],
+                }
+            );
+            let other_cluster =
+                usage_history(connection, "staging", "shop", &web, "2026-10-01T10:00:00Z").unwrap();
+            assert!(other_cluster.is_empty());
+        });
+    }
+
+    #[test]
+    fn reads_the_usage_history_through_the_workload_index() {
+        let store = store();
+        with_connection(&store, |connection| {
+            let plan: Vec<String> = connection
+                .prepare(&format!("EXPLAIN QUERY PLAN {}", USAGE_HISTORY))
+                .unwrap()
+                .query_map(params!["prod", "shop", "Deployment", "web", ""], |row| {
+                    row.get(3)
+                })
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert!(
+                plan.iter()
+                    .any(|step| step.contains("USING INDEX snapshot_pods_workload")),
+                "{:?}",
+                plan
+            );
+        });
+    }
+
+    #[test]
+    fn enables_incremental_vacuum() {
+        let store = store();
+        with_connection(&store, |connection| {
+            let auto_vacuum: i64 = connection
+                .query_row("PRAGMA auto_vacuum", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(auto_vacuum, 2);
+        });
+    }
+}
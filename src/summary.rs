@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+
+use axum::extract::{Json, State};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::cost::Cost;
+use crate::error::{ApiError, QueryError};
+use crate::quantity::DisplayUnits;
+use crate::selector;
+use crate::{
+    build_pod_query, cache_age_seconds, get_pods_info, reject_paging, resource_warnings,
+    watch_healthy, AppState, AppliedFilter, ContainerKind, DisplayResources, PodComputeInfo,
+    PodComputeInfoRequestBody, PodsInfo,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupBy {
+    Cluster,
+    Namespace,
+    Maintainer,
+    NodeName,
+    Label(String),
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SummaryRequestBody {
+    #[serde(flatten)]
+    pods: PodComputeInfoRequestBody,
+    group_by: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SummaryResponse {
+    filter: AppliedFilter,
+    cache_age_seconds: Option<f64>,
+    watch_healthy: Option<bool>,
+    units: DisplayUnits,
+    group_by: String,
+    warnings: Vec<String>,
+    errors: Vec<QueryError>,
+    groups: Vec<SummaryGroup>,
+}
+
+// Counts and requests cover app containers and sidecars, the same containers as the pod totals they sum.
+#[derive(Debug, Serialize, Clone, Default, JsonSchema)]
+pub struct SummaryGroup {
+    key: String,
+    pod_count: usize,
+    container_count: usize,
+    requested_cpu_millicores: u64,
+    requested_memory_bytes: u64,
+    display: DisplayResources,
+    cost: Option<Cost>,
+}
+
+impl GroupBy {
+    fn parse(s: &str) -> anyhow::Result<GroupBy> {
+        Ok(match s {
+            "cluster" => GroupBy::Cluster,
+            "namespace" => GroupBy::Namespace,
+            "maintainer" => GroupBy::Maintainer,
+            "node_name" => GroupBy::NodeName,
+            label_key => {
+                selector::validate_label_key(label_key)
+                    .map_err(|e| anyhow::anyhow!("invalid group_by: {}", e))?;
+                GroupBy::Label(label_key.to_string())
+            }
+        })
+    }
+
+    fn key(&self, pod: &PodComputeInfo) -> String {
+        match self {
+            GroupBy::Cluster => pod.cluster.clone(),
+            GroupBy::Namespace => pod.namespace.clone(),
+            GroupBy::Maintainer => pod.maintainer.clone(),
+            GroupBy::NodeName => pod.node_name.clone(),
+            GroupBy::Label(label_key) => pod
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.labels.as_ref())
+                .and_then(|labels| labels.get(label_key))
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub async fn get_summary(
+    State(state): State<AppState>,
+    Json(request_body): Json<SummaryRequestBody>,
+) -> Result<Json<SummaryResponse>, ApiError> {
+    let group_by = GroupBy::parse(&request_body.group_by).map_err(ApiError::bad_request)?;
+    reject_paging(&request_body.pods).map_err(ApiError::bad_request)?;
+    let mut query = build_pod_query(&state, &request_body.pods).map_err(ApiError::bad_request)?;
+    query.usage_metrics = false;
+    let PodsInfo {
+        pods,
+        errors,
+        mut warnings,
+    } = get_pods_info(&state, &query).await?;
+    warnings.extend(resource_warnings(&pods));
+    Ok(Json(SummaryResponse {
+        filter: query.applied_filter(request_body.pods.maintainers.unwrap_or_default()),
+        cache_age_seconds: cache_age_seconds(&state, &query),
+        watch_healthy: watch_healthy(&state, &query),
+        units: query.units,
+        group_by: request_body.group_by,
+        warnings,
+        errors,
+        groups: summarize(&pods, &group_by, query.units),
+    }))
+}
+
+pub fn summarize(
+    pods: &[PodComputeInfo],
+    group_by: &GroupBy,
+    units: DisplayUnits,
+) -> Vec<SummaryGroup> {
+    let mut groups: BTreeMap<String, SummaryGroup> = BTreeMap::new();
+    for pod in pods {
+        let key = group_by.key(pod);
+        let group = groups.entry(key.clone()).or_insert_with(|| SummaryGroup {
+            key,
+            ..Default::default()
+        });
+        group.pod_count += 1;
+        group.container_count += pod
+            .containers
+            .iter()
+            .filter(|c| c.kind != ContainerKind::Init)
+            .count();
+        group.requested_cpu_millicores += pod.totals.requested_cpu_millicores;
+        group.requested_memory_bytes += pod.totals.requested_memory_bytes;
+        group.cost = Cost::sum(group.cost, pod.cost);
+    }
+    groups
+        .into_values()
+        .map(|mut group| {
+            group.display = DisplayResources::new(
+                units,
+                Some(group.requested_cpu_millicores),
+                Some(group.requested_memory_bytes),
+                None,
+                None,
+            );
+            group
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod(namespace: &str, name: &str) -> PodComputeInfo {
+        crate::tests::pod_info(
+            "prod",
+            namespace,
+            name,
+            vec![crate::tests::spec_container("app", "100m", "64Mi")],
+        )
+    }
+
+    #[test]
+    fn groups_by_every_key() {
+        let mut web = pod("shop", "web");
+        web.maintainer = "team-a".to_string();
+        web.node_name = "node-1".to_string();
+        web.metadata = Some(crate::Metadata {
+            labels: Some(BTreeMap::from([(
+                "tier".to_string(),
+                "frontend".to_string(),
+            )])),
+        });
+        let mut api = pod("shop", "api");
+        api.cluster = "staging".to_string();
+        api.maintainer = "team-b".to_string();
+        api.node_name = "node-1".to_string();
+        let mut postgres = pod("db", "postgres");
+        postgres.maintainer = "team-b".to_string();
+        postgres.node_name = "node-2".to_string();
+        let pods = [web, api, postgres];
+
+        let groups = |group_by| -> Vec<(String, usize)> {
+            summarize(&pods, &group_by, DisplayUnits::default())
+                .into_iter()
+                .map(|group| (group.key, group.pod_count))
+                .collect()
+        };
+        let expected = |keys: &[(&str, usize)]| -> Vec<(String, usize)> {
+            keys.iter()
+                .map(|(key, count)| (key.to_string(), *count))
+                .collect()
+        };
+        assert_eq!(
+            groups(GroupBy::Cluster),
+            expected(&[("prod", 2), ("staging", 1)])
+        );
+        assert_eq!(
+            groups(GroupBy::Namespace),
+            expected(&[("db", 1), ("shop", 2)])
+        );
+        assert_eq!(
+            groups(GroupBy::Maintainer),
+            expected(&[("team-a", 1), ("team-b", 2)])
+        );
+        assert_eq!(
+            groups(GroupBy::NodeName),
+            expected(&[("node-1", 2), ("node-2", 1)])
+        );
+        // Pods without the label are grouped under an empty key.
+        assert_eq!(
+            groups(GroupBy::Label("tier".to_string())),
+            expected(&[("", 2), ("frontend", 1)])
+        );
+    }
+
+    #[test]
+    fn sums_requests_and_counts_containers_without_init_containers() {
+        let with_init = k8s_openapi::api::core::v1::Pod {
+            metadata: kube::api::ObjectMeta {
+                name: Some("api".to_string()),
+                namespace: Some("shop".to_string()),
+                ..Default::default()
+            },
+            spec: Some(k8s_openapi::api::core::v1::PodSpec {
+                containers: vec![crate::tests::spec_container("app", "200m", "128Mi")],
+                init_containers: Some(vec![crate::tests::spec_container("migrate", "1", "1Gi")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let pods = [
+            pod("shop", "web"),
+            crate::pod_compute_info(&with_init, "prod", &crate::tests::pod_query()),
+        ];
+        let groups = summarize(&pods, &GroupBy::Namespace, DisplayUnits::default());
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].pod_count, 2);
+        assert_eq!(groups[0].container_count, 2);
+        assert_eq!(groups[0].requested_cpu_millicores, 300);
+        assert_eq!(groups[0].requested_memory_bytes, 192 * 1024 * 1024);
+    }
+
+    #[test]
+    fn sums_the_cost_of_each_group() {
+        let priced = |namespace, name, hourly| {
+            let mut pod = pod(namespace, name);
+            pod.cost = Some(Cost {
+                hourly,
+                monthly: hourly * 730.0,
+            });
+            pod
+        };
+        let pods = [
+            priced("shop", "web", 0.5),
+            priced("shop", "api", 0.25),
+            pod("db", "postgres"),
+        ];
+        let groups = summarize(&pods, &GroupBy::Namespace, DisplayUnits::default());
+        assert_eq!(groups[0].key, "db");
+        assert_eq!(groups[0].cost, None);
+        assert_eq!(groups[1].key, "shop");
+        assert_eq!(
+            groups[1].cost,
+            Some(Cost {
+                hourly: 0.75,
+                monthly: 547.5
+            })
+        );
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use futures::{stream, StreamExt};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::{ApiResource, DynamicObject, ListParams};
+use kube::Api;
+use serde::Deserialize;
+
+use crate::cluster::Cluster;
+use crate::{PodComputeInfo, PodQuery};
+
+// Resource metrics served by metrics-server; there are no k8s-openapi types for them.
+fn pod_metrics_resource() -> ApiResource {
+    ApiResource {
+        group: "metrics.k8s.io".to_string(),
+        version: "v1beta1".to_string(),
+        api_version: "metrics.k8s.io/v1beta1".to_string(),
+        kind: "PodMetrics".to_string(),
+        plural: "pods".to_string(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainerMetrics {
+    name: String,
+    #[serde(default)]
+    usage: BTreeMap<String, Quantity>,
+}
+
+// Adds the current usage of every container of `pods`. Usage is best effort: when the metrics API is missing or
+// cannot be read the pods are left without usage and a warning is returned instead of an error.
+pub async fn add_usage(
+    cluster: &Cluster,
+    query: &PodQuery,
+    pods: &mut [PodComputeInfo],
+) -> Option<String> {
+    if pods.is_empty() {
+        return None;
+    }
+    let resource = pod_metrics_resource();
+    let apis: Vec<Api<DynamicObject>> = if query.all_namespaces {
+        vec![Api::all_with(cluster.client.clone(), &resource)]
+    } else {
+        pods.iter()
+            .map(|pod| pod.namespace.as_str())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|namespace| Api::namespaced_with(cluster.client.clone(), namespace, &resource))
+            .collect()
+    };
+    let mut list_params = ListParams::default();
+    if !query.label_selector.is_empty() {
+        list_params = list_params.labels(&query.label_selector.to_string());
+    }
+    let listings: Vec<kube::Result<Vec<DynamicObject>>> = stream::iter(apis)
+        .map(|api| {
+            let list_params = list_params.clone();
+            async move { Ok(api.list(&list_params).await?.items) }
+        })
+        .buffer_unordered(query.list_concurrency)
+        .collect()
+        .await;
+
+    let mut usage: HashMap<(String, String), Vec<ContainerMetrics>> = HashMap::new();
+    let mut failure = None;
+    for listing in listings {
+        match listing {
+            Ok(items) => {
+                for pod_metrics in items {
+                    let containers = pod_metrics
+                        .data
+                        .get("containers")
+                        .cloned()
+                        .and_then(|containers| serde_json::from_value(containers).ok())
+                        .unwrap_or_default();
+                    usage.insert(
+                        (
+                            pod_metrics.metadata.namespace.unwrap_or_default(),
+                            pod_metrics.metadata.name.unwrap_or_default(),
+                        ),
+                        containers,
+                    );
+                }
+            }
+            Err(e) => {
+                failure.get_or_insert(e);
+            }
+        }
+    }
+    for pod in pods {
+        let Some(metrics) = usage.get(&(pod.namespace.clone(), pod.name.clone())) else {
+            continue;
+        };
+        for container in &mut pod.containers {
+            if let Some(container_metrics) = metrics.iter().find(|m| m.name == container.name) {
+                container.set_usage(
+                    container_metrics.usage.get("cpu"),
+                    container_metrics.usage.get("memory"),
+                );
+            }
+        }
+    }
+    failure.map(|e| {
+        eprintln!(
+            "Error reading pod metrics of cluster {}: {}",
+            cluster.name, e
+        );
+        format!(
+            "usage is unavailable for cluster {}; is metrics-server installed? ({})",
+            cluster.name, e
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use axum::extract::Query;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+
+    fn pod(namespace: &str, name: &str) -> PodComputeInfo {
+        crate::tests::pod_info(
+            "test",
+            namespace,
+            name,
+            vec![
+                crate::tests::spec_container("app", "200m", "100Mi"),
+                crate::tests::spec_container("proxy", "100m", "64Mi"),
+            ],
+        )
+    }
+
+    fn pod_metrics_list() -> Value {
+        json!({
+            "apiVersion": "metrics.k8s.io/v1beta1",
+            "kind": "PodMetricsList",
+            "metadata": {},
+            "items": [
+                {
+                    "apiVersion": "metrics.k8s.io/v1beta1",
+                    "kind": "PodMetrics",
+                    "metadata": {"name": "web", "namespace": "shop"},
+                    "timestamp": "2024-05-01T10:00:00Z",
+                    "window": "30s",
+                    "containers": [
+                        {"name": "app", "usage": {"cpu": "150m", "memory": "50Mi"}},
+                        {"name": "proxy", "usage": {"cpu": "1234567n", "memory": "80Mi"}},
+                    ],
+                },
+                {
+                    "apiVersion": "metrics.k8s.io/v1beta1",
+                    "kind": "PodMetrics",
+                    "metadata": {"name": "gone", "namespace": "shop"},
+                    "timestamp": "2024-05-01T10:00:00Z",
+                    "window": "30s",
+                    "containers": [{"name": "app", "usage": {"cpu": "1", "memory": "1Gi"}}],
+                },
+            ],
+        })
+    }
+
+    async fn cluster(router: Router) -> Cluster {
+        Cluster {
+            name: "test".to_string(),
+            client: crate::tests::fake_apiserver(router).await,
+            pod_cache: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn adds_usage_from_cluster_wide_pod_metrics() {
+        let router = Router::new().route(
+            "/apis/metrics.k8s.io/v1beta1/pods",
+            get(|| async { Json(pod_metrics_list()) }),
+        );
+        let cluster = cluster(router).await;
+        let mut pods = [pod("shop", "web"), pod("shop", "api")];
+        let warning = add_usage(&cluster, &crate::tests::pod_query(), &mut pods).await;
+        assert_eq!(warning, None);
+
+        let app = &pods[0].containers[0];
+        assert_eq!(app.used_cpu, Some(Quantity("150m".to_string())));
+        assert_eq!(app.used_cpu_millicores, Some(150));
+        assert_eq!(app.used_memory_bytes, Some(50 * 1024 * 1024));
+        assert_eq!(app.cpu_usage_request_ratio, Some(0.75));
+        assert_eq!(app.memory_usage_request_ratio, Some(0.5));
+        let proxy = &pods[0].containers[1];
+        assert_eq!(proxy.used_cpu_millicores, Some(2));
+        assert_eq!(proxy.memory_usage_request_ratio, Some(1.25));
+        // A pod without metrics keeps no usage rather than zero.
+        assert!(pods[1]
+            .containers
+            .iter()
+            .all(|container| container.used_cpu_millicores.is_none()));
+    }
+
+    #[tokio::test]
+    async fn reads_pod_metrics_per_namespace_with_the_label_selector() {
+        let selectors = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&selectors);
+        let router = Router::new().route(
+            "/apis/metrics.k8s.io/v1beta1/namespaces/shop/pods",
+            get(
+                move |Query(params): Query<HashMap<String, String>>| async move {
+                    seen.lock()
+                        .unwrap()
+                        .push(params.get("labelSelector").cloned());
+                    Json(pod_metrics_list())
+                },
+            ),
+        );
+        let cluster = cluster(router).await;
+        let mut query = crate::tests::pod_query();
+        query.all_namespaces = false;
+        query.namespaces = vec!["shop".to_string()];
+        query.label_selector = "app=web".parse().unwrap();
+        let mut pods = [pod("shop", "web")];
+        assert_eq!(add_usage(&cluster, &query, &mut pods).await, None);
+        assert_eq!(pods[0].containers[0].used_cpu_millicores, Some(150));
+        assert_eq!(*selectors.lock().unwrap(), [Some("app=web".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn missing_metrics_api_is_a_warning() {
+        let cluster = cluster(Router::new()).await;
+        let mut pods = [pod("shop", "web")];
+        let warning = add_usage(&cluster, &crate::tests::pod_query(), &mut pods)
+            .await
+            .unwrap();
+        assert!(
+            warning.starts_with("usage is unavailable for cluster test"),
+            "{}",
+            warning
+        );
+        assert_eq!(pods[0].containers[0].used_cpu_millicores, None);
+    }
+}
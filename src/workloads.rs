@@ -0,0 +1,535 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::extract::{Json, State};
+use futures::{stream, StreamExt};
+use k8s_openapi::api::apps::v1::ReplicaSet;
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::Api;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::cluster::Cluster;
+use crate::error::{ApiError, QueryError};
+use crate::quantity::DisplayUnits;
+use crate::{
+    build_pod_query, cache_age_seconds, get_pods_info, reject_paging, resource_warnings,
+    watch_healthy, AppState, AppliedFilter, DisplayResources, PodComputeInfo,
+    PodComputeInfoRequestBody, PodQuery, PodsInfo,
+};
+
+// Controllers that are usually managed by another controller, which is reported in their place.
+const MANAGED_KINDS: &[(&str, &str)] = &[("ReplicaSet", "Deployment"), ("Job", "CronJob")];
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema)]
+pub struct Workload {
+    pub kind: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct WorkloadsResponse {
+    filter: AppliedFilter,
+    cache_age_seconds: Option<f64>,
+    watch_healthy: Option<bool>,
+    units: DisplayUnits,
+    warnings: Vec<String>,
+    errors: Vec<QueryError>,
+    workloads: Vec<WorkloadInfo>,
+}
+
+#[derive(Debug, Serialize, Clone, JsonSchema)]
+pub struct WorkloadInfo {
+    cluster: String,
+    namespace: String,
+    kind: String,
+    name: String,
+    maintainer: String,
+    replicas: usize,
+    requested_cpu_millicores: u64,
+    requested_memory_bytes: u64,
+    limit_cpu_millicores: Option<u64>,
+    limit_memory_bytes: Option<u64>,
+    display: DisplayResources,
+}
+
+// The pod's own controller, or the pod itself when nothing controls it.
+pub fn controller(pod: &Pod) -> Workload {
+    match controller_reference(pod.metadata.owner_references.as_deref()) {
+        Some(owner) => Workload {
+            kind: owner.kind.clone(),
+            name: owner.name.clone(),
+        },
+        None => Workload {
+            kind: "Pod".to_string(),
+            name: pod.metadata.name.clone().unwrap_or_default(),
+        },
+    }
+}
+
+fn controller_reference(owners: Option<&[OwnerReference]>) -> Option<&OwnerReference> {
+    owners?.iter().find(|owner| owner.controller == Some(true))
+}
+
+// Replaces ReplicaSets and Jobs with the Deployment or CronJob controlling them. Owners that cannot be looked up
+// leave the pod attributed to its direct controller, and a warning is returned; so does a pod cache that has not
+// listed them yet.
+pub async fn resolve_workloads(
+    cluster: &Cluster,
+    query: &PodQuery,
+    pods: &mut [PodComputeInfo],
+) -> Option<String> {
+    let mut managed: Vec<(String, Workload)> = pods
+        .iter()
+        .filter(|pod| {
+            MANAGED_KINDS
+                .iter()
+                .any(|(kind, _)| pod.workload.kind == *kind)
+        })
+        .map(|pod| (pod.namespace.clone(), pod.workload.clone()))
+        .collect();
+    managed.sort();
+    managed.dedup();
+    let lookups: Vec<_> = stream::iter(managed)
+        .map(|(namespace, workload)| async move {
+            let parent = parent_workload(cluster, &namespace, &workload).await;
+            ((namespace, workload), parent)
+        })
+        .buffer_unordered(query.list_concurrency)
+        .collect()
+        .await;
+    let mut parents = HashMap::new();
+    let mut failures = Vec::new();
+    for (key, lookup) in lookups {
+        match lookup {
+            Ok(Some(parent)) => {
+                parents.insert(key, parent);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!(
+                    "Error reading {} {}/{} in cluster {}: {}",
+                    key.1.kind, key.0, key.1.name, cluster.name, e
+                );
+                failures.push(e);
+            }
+        }
+    }
+    for pod in pods {
+        if let Some(parent) = parents.get(&(pod.namespace.clone(), pod.workload.clone())) {
+            pod.workload = parent.clone();
+        }
+    }
+    if cluster
+        .pod_cache
+        .as_ref()
+        .is_some_and(|cache| !cache.owners_ready())
+    {
+        return Some(format!(
+            "ReplicaSets and Jobs of cluster {} are still being listed, some pods may be reported under them",
+            cluster.name
+        ));
+    }
+    let failed = failures.len();
+    failures.into_iter().next().map(|e| {
+        format!(
+            "the owners of {} ReplicaSets or Jobs in cluster {} could not be read, their pods are reported under them ({})",
+            failed, cluster.name, e
+        )
+    })
+}
+
+// The Deployment or CronJob controlling a ReplicaSet or Job; `None` when it has no such owner or does not exist.
+async fn parent_workload(
+    cluster: &Cluster,
+    namespace: &str,
+    workload: &Workload,
+) -> kube::Result<Option<Workload>> {
+    let owners = match &cluster.pod_cache {
+        Some(cache) => cache.owner_references(&workload.kind, namespace, &workload.name),
+        None => {
+            let metadata = match workload.kind.as_str() {
+                "ReplicaSet" => Api::<ReplicaSet>::namespaced(cluster.client.clone(), namespace)
+                    .get_metadata_opt(&workload.name)
+                    .await?
+                    .map(|object| object.metadata),
+                "Job" => Api::<Job>::namespaced(cluster.client.clone(), namespace)
+                    .get_metadata_opt(&workload.name)
+                    .await?
+                    .map(|object| object.metadata),
+                _ => None,
+            };
+            metadata.map(|metadata| metadata.owner_references.unwrap_or_default())
+        }
+    };
+    let Some(owners) = owners else {
+        return Ok(None);
+    };
+    let parent = controller_reference(Some(&owners)).filter(|owner| {
+        MANAGED_KINDS
+            .iter()
+            .any(|(kind, parent)| workload.kind == *kind && owner.kind == *parent)
+    });
+    Ok(parent.map(|owner| Workload {
+        kind: owner.kind.clone(),
+        name: owner.name.clone(),
+    }))
+}
+
+pub async fn get_workloads(
+    State(state): State<AppState>,
+    Json(request_body): Json<PodComputeInfoRequestBody>,
+) -> Result<Json<WorkloadsResponse>, ApiError> {
+    reject_paging(&request_body).map_err(ApiError::bad_request)?;
+    let mut query = build_pod_query(&state, &request_body).map_err(ApiError::bad_request)?;
+    query.usage_metrics = false;
+    let PodsInfo {
+        pods,
+        errors,
+        mut warnings,
+    } = get_pods_info(&state, &query).await?;
+    warnings.extend(resource_warnings(&pods));
+    Ok(Json(WorkloadsResponse {
+        filter: query.applied_filter(request_body.maintainers.unwrap_or_default()),
+        cache_age_seconds: cache_age_seconds(&state, &query),
+        watch_healthy: watch_healthy(&state, &query),
+        units: query.units,
+        warnings,
+        errors,
+        workloads: aggregate(&pods, query.units),
+    }))
+}
+
+// One row per workload with the totals of all its replicas; limit totals are absent as soon as one replica is
+// unlimited, like the pod totals.
+pub fn aggregate(pods: &[PodComputeInfo], units: DisplayUnits) -> Vec<WorkloadInfo> {
+    let mut workloads: BTreeMap<(String, String, Workload), WorkloadInfo> = BTreeMap::new();
+    for pod in pods {
+        let key = (
+            pod.cluster.clone(),
+            pod.namespace.clone(),
+            pod.workload.clone(),
+        );
+        let workload = workloads.entry(key).or_insert_with(|| WorkloadInfo {
+            cluster: pod.cluster.clone(),
+            namespace: pod.namespace.clone(),
+            kind: pod.workload.kind.clone(),
+            name: pod.workload.name.clone(),
+            maintainer: pod.maintainer.clone(),
+            replicas: 0,
+            requested_cpu_millicores: 0,
+            requested_memory_bytes: 0,
+            limit_cpu_millicores: Some(0),
+            limit_memory_bytes: Some(0),
+            display: DisplayResources::default(),
+        });
+        workload.replicas += 1;
+        workload.requested_cpu_millicores += pod.totals.requested_cpu_millicores;
+        workload.requested_memory_bytes += pod.totals.requested_memory_bytes;
+        workload.limit_cpu_millicores = workload
+            .limit_cpu_millicores
+            .zip(pod.totals.limit_cpu_millicores)
+            .map(|(a, b)| a + b);
+        workload.limit_memory_bytes = workload
+            .limit_memory_bytes
+            .zip(pod.totals.limit_memory_bytes)
+            .map(|(a, b)| a + b);
+    }
+    workloads
+        .into_values()
+        .map(|mut workload| {
+            workload.display = DisplayResources::new(
+                units,
+                Some(workload.requested_cpu_millicores),
+                Some(workload.requested_memory_bytes),
+                workload.limit_cpu_millicores,
+                workload.limit_memory_bytes,
+            );
+            workload
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::cache::PodCache;
+    use axum::body::{Body, Bytes};
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::Router;
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use serde_json::{json, Value};
+
+    fn workload(kind: &str, name: &str) -> Workload {
+        Workload {
+            kind: kind.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn owner(kind: &str, name: &str, controller: bool) -> OwnerReference {
+        OwnerReference {
+            api_version: "apps/v1".to_string(),
+            kind: kind.to_string(),
+            name: name.to_string(),
+            uid: format!("{}-uid", name),
+            controller: Some(controller),
+            ..Default::default()
+        }
+    }
+
+    fn owner_json(kind: &str, name: &str) -> Value {
+        serde_json::to_value(owner(kind, name, true)).unwrap()
+    }
+
+    fn metadata(namespace: &str, name: &str, owners: Value) -> Value {
+        json!({
+            "apiVersion": "meta.k8s.io/v1",
+            "kind": "PartialObjectMetadata",
+            "metadata": {"name": name, "namespace": namespace, "ownerReferences": owners},
+        })
+    }
+
+    fn status(code: StatusCode, reason: &str) -> Response {
+        let body = json!({
+            "apiVersion": "v1",
+            "kind": "Status",
+            "metadata": {},
+            "status": "Failure",
+            "message": reason,
+            "reason": reason,
+            "code": code.as_u16(),
+        });
+        (code, axum::Json(body)).into_response()
+    }
+
+    // A pod of the workload in namespace `shop`, before its owner is resolved.
+    fn replica(name: &str, workload: Workload) -> PodComputeInfo {
+        let mut pod = crate::tests::pod_info(
+            "test",
+            "shop",
+            name,
+            vec![crate::tests::spec_container("app", "100m", "64Mi")],
+        );
+        pod.workload = workload;
+        pod
+    }
+
+    fn workloads(pods: &[PodComputeInfo]) -> Vec<&Workload> {
+        pods.iter().map(|pod| &pod.workload).collect()
+    }
+
+    async fn cluster(router: Router, pod_cache: bool) -> Cluster {
+        let client = crate::tests::fake_apiserver(router).await;
+        let pod_cache = pod_cache.then(|| PodCache::start(client.clone(), "test"));
+        Cluster {
+            name: "test".to_string(),
+            client,
+            pod_cache,
+        }
+    }
+
+    #[test]
+    fn controller_is_the_controlling_owner() {
+        let mut pod = Pod::default();
+        pod.metadata.name = Some("web-abc-1".to_string());
+        assert_eq!(controller(&pod), workload("Pod", "web-abc-1"));
+        pod.metadata.owner_references = Some(vec![owner("Node", "node-1", false)]);
+        assert_eq!(controller(&pod), workload("Pod", "web-abc-1"));
+        pod.metadata.owner_references = Some(vec![
+            owner("Node", "node-1", false),
+            owner("ReplicaSet", "web-abc", true),
+        ]);
+        assert_eq!(controller(&pod), workload("ReplicaSet", "web-abc"));
+    }
+
+    #[tokio::test]
+    async fn resolves_owners_with_get_requests() {
+        let router = Router::new()
+            .route(
+                "/apis/apps/v1/namespaces/shop/replicasets/web-abc",
+                get(|| async {
+                    axum::Json(metadata(
+                        "shop",
+                        "web-abc",
+                        json!([owner_json("Deployment", "web")]),
+                    ))
+                }),
+            )
+            .route(
+                "/apis/batch/v1/namespaces/shop/jobs/nightly-1",
+                get(|| async {
+                    axum::Json(metadata(
+                        "shop",
+                        "nightly-1",
+                        json!([owner_json("CronJob", "nightly")]),
+                    ))
+                }),
+            )
+            .route(
+                "/apis/batch/v1/namespaces/shop/jobs/manual",
+                get(|| async { axum::Json(metadata("shop", "manual", json!([]))) }),
+            )
+            .route(
+                "/apis/apps/v1/namespaces/shop/replicasets/gone",
+                get(|| async { status(StatusCode::NOT_FOUND, "NotFound") }),
+            );
+        let cluster = cluster(router, false).await;
+        let mut pods = [
+            replica("web-abc-1", workload("ReplicaSet", "web-abc")),
+            replica("web-abc-2", workload("ReplicaSet", "web-abc")),
+            replica("nightly-1-x", workload("Job", "nightly-1")),
+            replica("manual-x", workload("Job", "manual")),
+            replica("gone-x", workload("ReplicaSet", "gone")),
+            replica("db-0", workload("StatefulSet", "db")),
+        ];
+        let warning = resolve_workloads(&cluster, &crate::tests::pod_query(), &mut pods).await;
+        assert_eq!(warning, None);
+        assert_eq!(
+            workloads(&pods),
+            [
+                &workload("Deployment", "web"),
+                &workload("Deployment", "web"),
+                &workload("CronJob", "nightly"),
+                &workload("Job", "manual"),
+                &workload("ReplicaSet", "gone"),
+                &workload("StatefulSet", "db"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn warns_when_owners_cannot_be_read() {
+        let router = Router::new().route(
+            "/apis/apps/v1/namespaces/shop/replicasets/:name",
+            get(|| async { status(StatusCode::FORBIDDEN, "Forbidden") }),
+        );
+        let cluster = cluster(router, false).await;
+        let mut pods = [
+            replica("web-abc-1", workload("ReplicaSet", "web-abc")),
+            replica("api-def-1", workload("ReplicaSet", "api-def")),
+        ];
+        let warning = resolve_workloads(&cluster, &crate::tests::pod_query(), &mut pods)
+            .await
+            .unwrap();
+        assert!(
+            warning.starts_with(
+                "the owners of 2 ReplicaSets or Jobs in cluster test could not be read"
+            ),
+            "{}",
+            warning
+        );
+        assert_eq!(workloads(&pods)[0], &workload("ReplicaSet", "web-abc"));
+    }
+
+    // Lists `items` and then holds the watch open without sending anything.
+    async fn list(items: Value, params: HashMap<String, String>) -> Response {
+        if params.get("watch").is_some_and(|watch| watch == "true") {
+            let stream = futures::stream::pending::<Result<Bytes, std::io::Error>>();
+            return Body::from_stream(stream).into_response();
+        }
+        axum::Json(json!({
+            "apiVersion": "v1",
+            "kind": "List",
+            "metadata": {"resourceVersion": "1"},
+            "items": items,
+        }))
+        .into_response()
+    }
+
+    #[tokio::test]
+    async fn resolves_owners_from_the_pod_cache() {
+        let router = Router::new()
+            .route("/api/v1/pods", get(|Query(params)| list(json!([]), params)))
+            .route(
+                "/apis/apps/v1/replicasets",
+                get(|Query(params)| {
+                    let replica_set =
+                        metadata("shop", "web-abc", json!([owner_json("Deployment", "web")]));
+                    list(json!([replica_set]), params)
+                }),
+            )
+            .route(
+                "/apis/batch/v1/jobs",
+                get(|Query(params)| list(json!([]), params)),
+            );
+        let cluster = cluster(router, true).await;
+        let cache = cluster.pod_cache.as_ref().unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), cache.wait_until_ready())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut pods = [
+            replica("web-abc-1", workload("ReplicaSet", "web-abc")),
+            replica("unknown-1", workload("Job", "unknown")),
+        ];
+        let warning = resolve_workloads(&cluster, &crate::tests::pod_query(), &mut pods).await;
+        assert_eq!(warning, None);
+        assert_eq!(
+            workloads(&pods),
+            [&workload("Deployment", "web"), &workload("Job", "unknown")]
+        );
+    }
+
+    #[tokio::test]
+    async fn warns_while_the_pod_cache_has_not_listed_owners() {
+        let cluster = cluster(Router::new(), true).await;
+        let mut pods = [replica("web-abc-1", workload("ReplicaSet", "web-abc"))];
+        let warning = resolve_workloads(&cluster, &crate::tests::pod_query(), &mut pods)
+            .await
+            .unwrap();
+        assert!(warning.contains("are still being listed"), "{}", warning);
+        assert_eq!(workloads(&pods), [&workload("ReplicaSet", "web-abc")]);
+    }
+
+    #[test]
+    fn aggregates_replicas_per_workload() {
+        let limited = |name: &str, workload: Workload| {
+            let mut container = crate::tests::spec_container("app", "100m", "64Mi");
+            container.resources.as_mut().unwrap().limits = Some(
+                [
+                    ("cpu".to_string(), Quantity("200m".to_string())),
+                    ("memory".to_string(), Quantity("128Mi".to_string())),
+                ]
+                .into(),
+            );
+            let mut pod = crate::tests::pod_info("test", "shop", name, vec![container]);
+            pod.workload = workload;
+            pod
+        };
+        let mut unlimited = replica("api-1", workload("Deployment", "api"));
+        unlimited.maintainer = "team-a".to_string();
+        let pods = [
+            limited("web-1", workload("Deployment", "web")),
+            limited("web-2", workload("Deployment", "web")),
+            unlimited,
+            limited("api-2", workload("Deployment", "api")),
+        ];
+
+        let rows = aggregate(&pods, DisplayUnits::default());
+        assert_eq!(rows.len(), 2);
+        let (api, web) = (&rows[0], &rows[1]);
+        assert_eq!(
+            (api.kind.as_str(), api.name.as_str()),
+            ("Deployment", "api")
+        );
+        assert_eq!(api.replicas, 2);
+        assert_eq!(api.maintainer, "team-a");
+        assert_eq!(api.requested_cpu_millicores, 200);
+        // One replica without limits leaves the workload unlimited.
+        assert_eq!(api.limit_cpu_millicores, None);
+        assert_eq!(api.limit_memory_bytes, None);
+        assert_eq!(api.display.limit_cpu, None);
+        assert_eq!(web.replicas, 2);
+        assert_eq!(web.requested_memory_bytes, 128 * 1024 * 1024);
+        assert_eq!(web.limit_cpu_millicores, Some(400));
+        assert_eq!(web.limit_memory_bytes, Some(256 * 1024 * 1024));
+        assert_eq!(web.display.limit_cpu, Some(0.4));
+    }
+}